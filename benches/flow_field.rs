@@ -0,0 +1,124 @@
+//! How long chasing enemies take to work out their next move each tick:
+//! one shared flow field against every enemy asking the navmesh for a path
+//! of its own.
+
+use bevy::ecs::system::System;
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use maze_shooter::enemy::{update_flow_field, FlowField};
+use maze_shooter::level::{build_nav_mesh, wall_pieces, LevelConfig, MazeGrid, MazeLayout};
+use maze_shooter::player::Player;
+use oxidized_navigation::query::find_path;
+use oxidized_navigation::{NavMesh, NavMeshSettings};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const ENEMY_COUNTS: [usize; 3] = [40, 200, 1000];
+
+/// The default level's maze and navmesh, its player and `enemies` enemies
+/// scattered over it.
+struct Level {
+    world: World,
+    nav_mesh: (NavMeshSettings, NavMesh),
+    player: Vec3,
+    enemies: Vec<Vec3>,
+}
+
+fn level(enemies: usize) -> Level {
+    let config = LevelConfig::default();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut maze =
+        config
+            .algorithm
+            .generator()
+            .generate(config.maze_width, config.maze_height, &mut rng);
+    maze.remove_random_walls(config.removed_walls, &mut rng);
+    let grid = MazeGrid::new(config.maze_width, config.maze_height, &config);
+    let player = grid.cell_center((0, 0), 1.5);
+    let enemies = (0..enemies)
+        .map(|_| {
+            let cell = (
+                rng.gen_range(0..config.maze_width),
+                rng.gen_range(0..config.maze_height),
+            );
+            grid.cell_center(cell, 1.5)
+        })
+        .collect();
+
+    let nav_mesh = build_nav_mesh(&grid, &wall_pieces(&maze, grid, config.wall_height));
+
+    let mut world = World::new();
+    world.insert_resource(maze);
+    world.insert_resource(grid);
+    world.init_resource::<FlowField>();
+    world.spawn((
+        Transform::from_translation(player),
+        Player {
+            index: 0,
+            ammunition: 0,
+            last_shot_time: None,
+            last_hit_time: None,
+            aim: 0.0,
+            regen_time: 1.0,
+        },
+    ));
+    Level {
+        world,
+        nav_mesh,
+        player,
+        enemies,
+    }
+}
+
+fn chase(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chase");
+    for count in ENEMY_COUNTS {
+        let Level {
+            mut world,
+            nav_mesh: (settings, nav_mesh),
+            player,
+            enemies,
+        } = level(count);
+
+        // The worst tick for the flow field: the player has just moved to
+        // another cell, so it is rebuilt before anyone reads it.
+        let mut rebuild = IntoSystem::into_system(update_flow_field);
+        rebuild.initialize(&mut world);
+        group.bench_with_input(
+            BenchmarkId::new("flow_field", count),
+            &enemies,
+            |b, enemies| {
+                b.iter(|| {
+                    *world.resource_mut::<FlowField>() = FlowField::default();
+                    rebuild.run((), &mut world);
+                    let field = world.resource::<FlowField>();
+                    let maze = world.resource::<MazeLayout>();
+                    let grid = world.resource::<MazeGrid>();
+                    for &enemy in enemies {
+                        black_box(field.next_waypoint(maze, grid, enemy, player));
+                    }
+                })
+            },
+        );
+
+        // The baseline: every enemy finds its own way to the player, as
+        // they did before the flow field.
+        let tiles = nav_mesh.get();
+        let tiles = tiles.read().unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("per_enemy", count),
+            &enemies,
+            |b, enemies| {
+                b.iter(|| {
+                    for &enemy in enemies {
+                        black_box(find_path(&tiles, &settings, enemy, player, None, None).ok());
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, chase);
+criterion_main!(benches);
@@ -0,0 +1,280 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+use bevy::prelude::*;
+use leafwing_input_manager::{prelude::*, user_input::InputKind};
+use serde::{Deserialize, Serialize};
+
+use crate::input::{Action, InputDevices};
+
+/// Actions that can be rebound from the controls menu, in the order they
+/// are listed.  `Move` and `Aim` are stick axes and keep their defaults.
+pub const REBINDABLE: [Action; 12] = [
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::AimUp,
+    Action::AimDown,
+    Action::AimLeft,
+    Action::AimRight,
+    Action::Shoot,
+    Action::Pause,
+    Action::ToggleFps,
+    Action::ToggleAiDebug,
+];
+
+/// Which inputs trigger which actions.  Loaded from the user's config
+/// directory on startup and saved there whenever it is changed in the
+/// controls menu.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Bindings(pub Vec<(InputKind, Action)>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings(vec![
+            (InputKind::Keyboard(KeyCode::W), Action::MoveUp),
+            (InputKind::Keyboard(KeyCode::A), Action::MoveLeft),
+            (InputKind::Keyboard(KeyCode::S), Action::MoveDown),
+            (InputKind::Keyboard(KeyCode::D), Action::MoveRight),
+            (InputKind::Keyboard(KeyCode::Left), Action::AimLeft),
+            (InputKind::Keyboard(KeyCode::Right), Action::AimRight),
+            (InputKind::Keyboard(KeyCode::Up), Action::AimUp),
+            (InputKind::Keyboard(KeyCode::Down), Action::AimDown),
+            (InputKind::DualAxis(DualAxis::left_stick()), Action::Move),
+            (InputKind::DualAxis(DualAxis::right_stick()), Action::Aim),
+            (InputKind::Keyboard(KeyCode::Space), Action::Shoot),
+            (InputKind::Mouse(MouseButton::Left), Action::Shoot),
+            (
+                InputKind::GamepadButton(GamepadButtonType::RightTrigger),
+                Action::Shoot,
+            ),
+            (InputKind::Keyboard(KeyCode::F), Action::ToggleFps),
+            (InputKind::Keyboard(KeyCode::F3), Action::ToggleAiDebug),
+            (InputKind::Keyboard(KeyCode::Escape), Action::Pause),
+            (
+                InputKind::GamepadButton(GamepadButtonType::Start),
+                Action::Pause,
+            ),
+        ])
+    }
+}
+
+impl Bindings {
+    /// Reads the saved bindings, falling back to the defaults if there
+    /// aren't any or they can't be read.
+    pub fn load() -> Self {
+        let Some(path) = bindings_path() else {
+            return Bindings::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|e| {
+                warn!("Ignoring bad bindings file {}: {}", path.display(), e);
+                Bindings::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Bindings::default(),
+            Err(e) => {
+                warn!("Couldn't read bindings file {}: {}", path.display(), e);
+                Bindings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = bindings_path().ok_or("no config directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// The input map for a player using `devices`: keyboard and mouse
+    /// bindings only if it has them, and gamepad ones for its own gamepad.
+    pub fn input_map_for(&self, devices: InputDevices) -> InputMap<Action> {
+        let mut input_map =
+            InputMap::new(self.0.iter().cloned().filter(|&(input, _)| {
+                devices.keyboard || Slot::of(input) != Slot::KeyboardAndMouse
+            }));
+        if let Some(gamepad) = devices.gamepad {
+            input_map.set_gamepad(gamepad);
+        }
+        input_map
+    }
+
+    /// The inputs bound to `action`.
+    pub fn inputs(&self, action: Action) -> impl Iterator<Item = InputKind> + '_ {
+        self.0
+            .iter()
+            .filter(move |(_, bound)| *bound == action)
+            .map(|(input, _)| *input)
+    }
+
+    /// Binds `input` to `action` in place of whatever `action` had on the
+    /// same device.  If `input` already belonged to another action, that
+    /// action gets the replaced binding instead, or loses `input` without
+    /// a replacement if there wasn't one.
+    pub fn rebind(&mut self, action: Action, input: InputKind) -> Option<Displaced> {
+        let slot = Slot::of(input);
+        let replaced: Vec<InputKind> = self
+            .inputs(action)
+            .filter(|&old| Slot::of(old) == slot)
+            .collect();
+        let conflict = self
+            .0
+            .iter()
+            .find(|&&(bound, other)| bound == input && other != action)
+            .map(|&(_, other)| other);
+        self.0.retain(|&(bound, bound_action)| {
+            bound != input && !(bound_action == action && replaced.contains(&bound))
+        });
+        self.0.push((input, action));
+        let other = conflict?;
+        match replaced.first() {
+            Some(&old) => {
+                self.0.push((old, other));
+                Some(Displaced::Swapped(other))
+            }
+            None => Some(Displaced::Unbound(other)),
+        }
+    }
+}
+
+/// What [`Bindings::rebind`] did to the action whose input was taken.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Displaced {
+    /// It was given the rebound action's old input on that device.
+    Swapped(Action),
+    /// The rebound action had nothing on that device to give it, so it is
+    /// left without a binding there.
+    Unbound(Action),
+}
+
+pub fn load_bindings(mut commands: Commands) {
+    commands.insert_resource(Bindings::load());
+}
+
+/// Which device an input is on.  Rebinding an action only replaces its
+/// binding on the same device, so keyboard and gamepad can be changed
+/// separately.
+#[derive(PartialEq, Eq)]
+enum Slot {
+    KeyboardAndMouse,
+    Gamepad,
+    Other,
+}
+
+impl Slot {
+    fn of(input: InputKind) -> Self {
+        match input {
+            InputKind::Keyboard(_) | InputKind::Mouse(_) => Slot::KeyboardAndMouse,
+            InputKind::GamepadButton(_) => Slot::Gamepad,
+            _ => Slot::Other,
+        }
+    }
+}
+
+/// A short name for an input, for menus.
+pub fn describe(input: InputKind) -> String {
+    match input {
+        InputKind::Keyboard(key) => format!("{:?}", key),
+        InputKind::Mouse(button) => format!("Mouse {:?}", button),
+        InputKind::GamepadButton(button) => format!("Pad {:?}", button),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Where the bindings file lives: `maze_shooter/bindings.ron` in the
+/// platform's usual config directory.
+fn bindings_path() -> Option<PathBuf> {
+    Some(
+        config_dir(|name| env::var_os(name))?
+            .join("maze_shooter")
+            .join("bindings.ron"),
+    )
+}
+
+/// The platform's usual config directory, worked out from the environment
+/// variables `var` looks up.
+fn config_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        var("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_to_a_taken_input_swaps_the_bindings() {
+        let mut bindings = Bindings::default();
+        let conflict = bindings.rebind(Action::AimUp, InputKind::Keyboard(KeyCode::W));
+        assert_eq!(conflict, Some(Displaced::Swapped(Action::MoveUp)));
+        let aim_up: Vec<InputKind> = bindings.inputs(Action::AimUp).collect();
+        let move_up: Vec<InputKind> = bindings.inputs(Action::MoveUp).collect();
+        assert_eq!(aim_up, vec![InputKind::Keyboard(KeyCode::W)]);
+        assert_eq!(move_up, vec![InputKind::Keyboard(KeyCode::Up)]);
+    }
+
+    #[test]
+    fn taking_an_input_with_nothing_to_swap_unbinds_the_other_action() {
+        let mut bindings = Bindings::default();
+        // The FPS toggle has no gamepad button to hand over to Pause.
+        let start = InputKind::GamepadButton(GamepadButtonType::Start);
+        let conflict = bindings.rebind(Action::ToggleFps, start);
+        assert_eq!(conflict, Some(Displaced::Unbound(Action::Pause)));
+        let pause: Vec<InputKind> = bindings.inputs(Action::Pause).collect();
+        let toggle_fps: Vec<InputKind> = bindings.inputs(Action::ToggleFps).collect();
+        assert_eq!(pause, vec![InputKind::Keyboard(KeyCode::Escape)]);
+        assert_eq!(toggle_fps, vec![InputKind::Keyboard(KeyCode::F), start]);
+    }
+
+    #[test]
+    fn rebinding_keeps_other_devices() {
+        let mut bindings = Bindings::default();
+        let conflict = bindings.rebind(Action::Pause, InputKind::Keyboard(KeyCode::P));
+        assert_eq!(conflict, None);
+        let pause: Vec<InputKind> = bindings.inputs(Action::Pause).collect();
+        assert_eq!(pause.len(), 2);
+        assert!(pause.contains(&InputKind::Keyboard(KeyCode::P)));
+        assert!(pause.contains(&InputKind::GamepadButton(GamepadButtonType::Start)));
+    }
+
+    #[test]
+    fn bindings_survive_a_ron_round_trip() {
+        let mut bindings = Bindings::default();
+        bindings.rebind(Action::Shoot, InputKind::Mouse(MouseButton::Right));
+        let text = ron::ser::to_string_pretty(&bindings, ron::ser::PrettyConfig::default())
+            .expect("bindings serialize");
+        let loaded: Bindings = ron::from_str(&text).expect("bindings deserialize");
+        assert_eq!(loaded, bindings);
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn config_dir_prefers_an_absolute_xdg_config_home() {
+        let env = |xdg: &'static str| {
+            move |name: &str| match name {
+                "XDG_CONFIG_HOME" => Some(OsString::from(xdg)),
+                "HOME" => Some(OsString::from("/home/player")),
+                _ => None,
+            }
+        };
+        assert_eq!(config_dir(env("/xdg")), Some(PathBuf::from("/xdg")));
+        assert_eq!(
+            config_dir(env("relative")),
+            Some(PathBuf::from("/home/player/.config"))
+        );
+        assert_eq!(config_dir(|_| None), None);
+    }
+}
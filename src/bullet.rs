@@ -1,46 +1,296 @@
 use bevy::prelude::*;
 use bevy_xpbd_3d::prelude::*;
 
-use crate::enemy::{Enemy, EnemyCounts};
+use crate::character_controller as cc;
+use crate::enemy::{self, Dying, Enemy, EnemyCounts};
+use crate::health::Health;
+use crate::interpolation::Interpolated;
+use crate::level::{LevelEntity, Wall};
+use crate::player::{Player, PlayerShot};
+use crate::stats::RunStats;
 
 const BULLET_SPEED: f32 = 20.0;
+const BULLET_DAMAGE: i32 = 1;
+const BULLET_KNOCKBACK: f32 = 4.0;
+const BULLET_LIFETIME: f32 = 3.0;
+const BULLET_RANGE: f32 = 50.0;
+const IMPACT_TIME: f32 = 0.25;
 
+/// Which side fired a bullet.  Bullets pass through their own side.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Team {
+    Player,
+    Enemies,
+}
+
+#[derive(Component)]
+pub struct Bullet {
+    pub damage: i32,
+    pub owner: Entity,
+    pub team: Team,
+    origin: Vec3,
+    fired_at: f32,
+    /// Set once it has hit something and is on its way out, so it isn't
+    /// also expired.
+    spent: bool,
+}
+
+/// A short-lived puff left where a bullet hits a wall.
 #[derive(Component)]
-pub struct Bullet {}
+pub struct Impact {
+    since: f32,
+}
+
+#[derive(Resource)]
+pub struct ImpactEffect {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub fn setup_impact_effect(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(ImpactEffect {
+        mesh: meshes.add(
+            shape::UVSphere {
+                radius: 0.2,
+                ..default()
+            }
+            .into(),
+        ),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgba(1.0, 0.8, 0.3, 0.8),
+            emissive: Color::rgb(1.0, 0.6, 0.1),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
 
 pub fn spawn_bullet(
     commands: &mut Commands,
-    assets: ResMut<AssetServer>,
+    assets: &AssetServer,
+    time: &Time,
+    owner: Entity,
+    team: Team,
     dir: Vec3,
     base: &Transform,
 ) {
-    let mut bullet_pos = base.translation + dir.clone() * 1.0;
+    let bullet_pos = base.translation + dir * 1.0;
+    let transform = Transform::from_translation(bullet_pos);
     commands
         .spawn(SceneBundle {
             scene: assets.load("bullet.gltf#Scene0"),
-            transform: Transform::from_translation(bullet_pos),
+            transform,
             ..Default::default()
         })
-        .insert(Bullet {})
+        .insert(Interpolated::new(transform))
+        .insert(Bullet {
+            damage: BULLET_DAMAGE,
+            owner,
+            team,
+            origin: bullet_pos,
+            fired_at: time.elapsed_seconds(),
+            spent: false,
+        })
+        .insert(LevelEntity)
         .insert(LinearVelocity(dir * BULLET_SPEED))
         .insert(RigidBody::Kinematic)
         .insert(Collider::ball(0.1))
         .insert(Sensor);
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn hit_bullet(
     mut commands: Commands,
-    query: Query<(&Bullet, Entity, &CollidingEntities)>,
-    enemy_query: Query<(&Enemy, Entity)>,
+    time: Res<Time>,
+    mut query: Query<(
+        &mut Bullet,
+        Entity,
+        &CollidingEntities,
+        &LinearVelocity,
+        &Transform,
+    )>,
+    wall_query: Query<(), With<Wall>>,
+    players: Query<(), With<Player>>,
+    enemies: Query<(), With<Enemy>>,
+    impact_effect: Res<ImpactEffect>,
+    mut enemy_query: Query<(&mut Enemy, Entity, &mut Health, &mut cc::Knockback), Without<Dying>>,
+    mut player_shots: EventWriter<PlayerShot>,
     mut enemy_counts: ResMut<EnemyCounts>,
+    mut stats: ResMut<RunStats>,
 ) {
-    for (_bullet, bullet_entity, colliders) in query.iter() {
-        if colliders.len() > 0 {
+    for (mut bullet, bullet_entity, colliders, velocity, xform) in query.iter_mut() {
+        let same_team = |entity: &Entity| match bullet.team {
+            Team::Player => players.contains(*entity),
+            Team::Enemies => enemies.contains(*entity),
+        };
+        let hits: Vec<Entity> = colliders
+            .iter()
+            .filter(|e| **e != bullet.owner && !same_team(e))
+            .cloned()
+            .collect();
+        if !hits.is_empty() {
+            bullet.spent = true;
             commands.entity(bullet_entity).despawn_recursive();
-            for (_, enemy_entity) in enemy_query.iter().filter(|(_, ee)| colliders.contains(ee)) {
-                commands.entity(enemy_entity).despawn_recursive();
-                enemy_counts.killed += 1;
+            if hits.iter().any(|e| wall_query.contains(*e)) {
+                commands.spawn((
+                    PbrBundle {
+                        mesh: impact_effect.mesh.clone(),
+                        material: impact_effect.material.clone(),
+                        transform: Transform::from_translation(xform.translation),
+                        ..default()
+                    },
+                    Impact {
+                        since: time.elapsed_seconds(),
+                    },
+                    LevelEntity,
+                ));
+            }
+            if bullet.team == Team::Enemies {
+                for player in hits.iter().filter(|e| players.contains(**e)) {
+                    player_shots.send(PlayerShot {
+                        player: *player,
+                        damage: bullet.damage,
+                        from: xform.translation - velocity.0.normalize_or_zero(),
+                    });
+                }
+                continue;
             }
+            let mut hit = false;
+            for (mut enemy, enemy_entity, mut health, mut knockback) in enemy_query.iter_mut() {
+                // Two bullets can reach the same enemy in one frame, so skip
+                // anything already killed before `Dying` gets inserted.
+                if !hits.contains(&enemy_entity) || health.is_dead() {
+                    continue;
+                }
+                hit = true;
+                health.damage(bullet.damage);
+                if health.is_dead() {
+                    enemy::kill_enemy(&mut commands, enemy_entity, &time);
+                    enemy_counts.killed += 1;
+                } else {
+                    enemy.last_hit_time = Some(time.elapsed_seconds());
+                    knockback.0 = velocity.0.normalize_or_zero() * BULLET_KNOCKBACK;
+                }
+            }
+            if hit {
+                stats.hits += 1;
+            }
+        }
+    }
+}
+
+/// Removes bullets that have been flying too long or too far without
+/// hitting anything.  Runs after `hit_bullet`, which despawns the ones that
+/// did.
+pub fn expire_bullets(
+    mut commands: Commands,
+    time: Res<Time>,
+    query: Query<(Entity, &Bullet, &Transform)>,
+) {
+    for (entity, bullet, xform) in query.iter() {
+        if !bullet.spent
+            && (time.elapsed_seconds() - bullet.fired_at > BULLET_LIFETIME
+                || xform.translation.distance(bullet.origin) > BULLET_RANGE)
+        {
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
+
+pub fn fade_impacts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &Impact, &mut Transform)>,
+) {
+    for (entity, impact, mut xform) in query.iter_mut() {
+        let age = time.elapsed_seconds() - impact.since;
+        if age > IMPACT_TIME {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            xform.scale = Vec3::splat(1.0 + 2.0 * age / IMPACT_TIME);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::controller::Intent;
+    use crate::gameplay::{headless_app, spawn_test_player, GameplaySet};
+
+    /// Runs `app` until the bullet it fires is gone and returns how long it
+    /// lasted.  Nothing is in its way, so only expiring can remove it.
+    fn lifespan(app: &mut App) -> f32 {
+        let mut bullets = app.world.query::<&Bullet>();
+        let mut fired_at = None;
+        for _ in 0..(BULLET_LIFETIME * 64.0) as usize * 2 {
+            app.update();
+            match (bullets.iter(&app.world).next(), fired_at) {
+                (Some(bullet), None) => fired_at = Some(bullet.fired_at),
+                (None, Some(fired_at)) => {
+                    return app.world.resource::<Time<Fixed>>().elapsed_seconds() - fired_at;
+                }
+                _ => {}
+            }
+        }
+        panic!("the bullet never expired");
+    }
+
+    /// Close enough to `expected` seconds, given bullets are only checked
+    /// once a tick.
+    fn assert_about(seconds: f32, expected: f32) {
+        assert!(
+            (seconds - expected).abs() <= 2.0 / 64.0,
+            "lasted {seconds}s, expected {expected}s"
+        );
+    }
+
+    #[test]
+    fn fired_bullets_expire_at_their_range() {
+        let mut app = headless_app(Duration::from_secs_f64(1.0 / 64.0));
+        app.update();
+        let player = spawn_test_player(&mut app, 0.0, 0.0);
+        app.world.get_mut::<Intent>(player).unwrap().fire = true;
+        // Full speed reaches the range before the lifetime is up.
+        assert_about(lifespan(&mut app), BULLET_RANGE / BULLET_SPEED);
+    }
+
+    /// Fires one bullet at half speed on the first tick.
+    fn fire_slow_bullet(
+        mut commands: Commands,
+        assets: Res<AssetServer>,
+        time: Res<Time>,
+        mut fired: Local<bool>,
+    ) {
+        if !std::mem::replace(&mut *fired, true) {
+            let owner = commands.spawn_empty().id();
+            spawn_bullet(
+                &mut commands,
+                &assets,
+                &time,
+                owner,
+                Team::Player,
+                Vec3::X * 0.5,
+                &Transform::IDENTITY,
+            );
+        }
+    }
+
+    #[test]
+    fn slow_bullets_expire_after_their_lifetime() {
+        let mut app = headless_app(Duration::from_secs_f64(1.0 / 64.0));
+        app.add_systems(
+            FixedUpdate,
+            fire_slow_bullet.in_set(GameplaySet).before(hit_bullet),
+        );
+        // Half speed runs out of time before it gets out of range.
+        assert_about(lifespan(&mut app), BULLET_LIFETIME);
+    }
+}
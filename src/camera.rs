@@ -0,0 +1,238 @@
+use std::f32::consts::FRAC_PI_8;
+
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::window::PrimaryWindow;
+
+use crate::level::LevelEntity;
+use crate::player::{Player, PlayerUi};
+
+/// How far the followed point can get from the camera before it moves.
+const FOLLOW_THRESHOLD: f32 = 3.0;
+const FOLLOW_SPEED: f32 = 6.0;
+/// Draw order of the camera that puts the HUD over the split screen.
+const UI_CAMERA_ORDER: isize = 10;
+
+/// Whether co-op players share one camera or each get a part of the
+/// window, chosen on the title screen.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CameraMode {
+    #[default]
+    Shared,
+    SplitScreen,
+}
+
+impl CameraMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            CameraMode::Shared => CameraMode::SplitScreen,
+            CameraMode::SplitScreen => CameraMode::Shared,
+        }
+    }
+}
+
+/// A split-screen camera and the player it follows.
+#[derive(Component)]
+pub struct PlayerCamera {
+    pub player: Entity,
+    pub index: usize,
+}
+
+/// Draws the HUD across the whole window while the player cameras each
+/// draw the world into their own part of it.
+#[derive(Component)]
+pub struct UiCamera;
+
+/// Moves `camera` towards `target` on the ground, once it gets far enough
+/// away from the camera.
+fn follow(camera: &mut Transform, target: Vec3, delta: f32) {
+    let mut camera_projection = camera.translation;
+    camera_projection.y = 0.0;
+    let mut target_projection = target;
+    target_projection.y = 0.0;
+
+    let distance = target_projection - camera_projection;
+    if distance.length() > FOLLOW_THRESHOLD {
+        camera.translation += distance / distance.length() * FOLLOW_SPEED * delta;
+    }
+}
+
+/// Follows the players with the shared camera, rising to keep them all in
+/// view.
+#[allow(clippy::type_complexity)]
+pub fn move_camera(
+    mut camera_query: Query<
+        &mut Transform,
+        (With<Camera3d>, Without<PlayerCamera>, Without<Player>),
+    >,
+    player_query: Query<&Transform, With<Player>>,
+    time: Res<Time>,
+) {
+    let Ok(mut camera_transform) = camera_query.get_single_mut() else {
+        return;
+    };
+    let player_projections: Vec<Vec3> = player_query
+        .iter()
+        .map(|xform| Vec3::new(xform.translation.x, 0.0, xform.translation.z))
+        .collect();
+    if player_projections.is_empty() {
+        return;
+    }
+    let centre = player_projections.iter().sum::<Vec3>() / player_projections.len() as f32;
+    let spread = player_projections
+        .iter()
+        .map(|projection| projection.distance(centre))
+        .fold(0.0, f32::max);
+    let delta = time.delta_seconds();
+    let min_height = 25.0;
+    let margin = 6.0;
+    follow(&mut camera_transform, centre, delta);
+    // High enough that everyone fits in the default 45 degree field of view.
+    let height = ((spread + margin) / FRAC_PI_8.tan()).max(min_height);
+    camera_transform.translation.y += (height - camera_transform.translation.y) * delta;
+}
+
+/// Follows each player with its own split-screen camera.
+pub fn move_player_cameras(
+    time: Res<Time>,
+    player_query: Query<&Transform, (With<Player>, Without<PlayerCamera>)>,
+    mut camera_query: Query<(&PlayerCamera, &mut Transform), Without<Player>>,
+) {
+    for (follows, mut camera_transform) in camera_query.iter_mut() {
+        if let Ok(player_xform) = player_query.get(follows.player) {
+            follow(
+                &mut camera_transform,
+                player_xform.translation,
+                time.delta_seconds(),
+            );
+        }
+    }
+}
+
+/// In split-screen mode, gives each player a camera of its own when a
+/// co-op level starts, plus one to draw the HUD over them.
+pub fn add_player_cameras(
+    mut commands: Commands,
+    mode: Res<CameraMode>,
+    added: Query<(), Added<Player>>,
+    player_query: Query<(Entity, &Player)>,
+    main_camera: Query<&Transform, (With<Camera3d>, Without<PlayerCamera>)>,
+) {
+    if *mode != CameraMode::SplitScreen || added.is_empty() || player_query.iter().count() < 2 {
+        return;
+    }
+    let Ok(&start) = main_camera.get_single() else {
+        return;
+    };
+    for (entity, player) in player_query.iter() {
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    // Drawn after the shared camera, which stays at 0.
+                    order: 1 + player.index as isize,
+                    ..default()
+                },
+                transform: start,
+                ..default()
+            },
+            UiCameraConfig { show_ui: false },
+            PlayerCamera {
+                player: entity,
+                index: player.index,
+            },
+            LevelEntity,
+        ));
+    }
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                order: UI_CAMERA_ORDER,
+                ..default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..default()
+        },
+        UiCamera,
+        LevelEntity,
+    ));
+}
+
+/// Splits the window between the player cameras, side by side for two and
+/// in a grid for more, and moves each player's HUD panel into its part.
+/// A downed player's camera and panel go, and the others close up to fill
+/// the space.  The shared camera is switched off while there are player
+/// cameras.
+pub fn set_viewports(
+    mut commands: Commands,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    players: Query<(), With<Player>>,
+    mut main_camera: Query<&mut Camera, (With<Camera3d>, Without<PlayerCamera>)>,
+    mut camera_query: Query<(Entity, &PlayerCamera, &mut Camera)>,
+    mut panels: Query<(&PlayerUi, &mut Style)>,
+) {
+    let mut cameras = Vec::new();
+    for (entity, follows, camera) in camera_query.iter_mut() {
+        if players.contains(follows.player) {
+            cameras.push((follows, camera));
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    cameras.sort_by_key(|(follows, _)| follows.index);
+    let count = cameras.len();
+    for mut camera in main_camera.iter_mut() {
+        if camera.is_active != (count == 0) {
+            camera.is_active = count == 0;
+        }
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if count == 0 || window.physical_width() == 0 || window.physical_height() == 0 {
+        return;
+    }
+    let columns = count.min(2) as u32;
+    let rows = (count as u32).div_ceil(columns);
+    let cell = UVec2::new(
+        window.physical_width() / columns,
+        window.physical_height() / rows,
+    );
+    let scale = window.scale_factor() as f32;
+    // A downed player's panel has nowhere left to go.
+    for (ui, mut style) in panels.iter_mut() {
+        let shown = cameras.iter().any(|(follows, _)| follows.index == ui.index);
+        if !shown && style.display != Display::None {
+            style.display = Display::None;
+        }
+    }
+    for (slot, (follows, mut camera)) in cameras.into_iter().enumerate() {
+        let column = slot as u32 % columns;
+        let position = UVec2::new(column, slot as u32 / columns) * cell;
+        let mut size = cell;
+        // The last camera of an odd number stretches across its row.
+        if slot + 1 == count && column == 0 {
+            size.x = window.physical_width();
+        }
+        let unchanged = camera.viewport.as_ref().is_some_and(|viewport| {
+            viewport.physical_position == position && viewport.physical_size == size
+        });
+        if !unchanged {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: size,
+                ..default()
+            });
+        }
+        for (ui, mut style) in panels.iter_mut() {
+            let left = Val::Px(position.x as f32 / scale + 5.0);
+            let bottom = Val::Px(window.height() - (position.y + size.y) as f32 / scale + 5.0);
+            if ui.index == follows.index && (style.left != left || style.bottom != bottom) {
+                style.left = left;
+                style.bottom = bottom;
+            }
+        }
+    }
+}
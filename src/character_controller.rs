@@ -1,19 +1,27 @@
-use bevy::{ecs::query::Has, prelude::*};
+use bevy::prelude::*;
 use bevy_xpbd_3d::{math::*, prelude::*, SubstepSchedule, SubstepSet};
 
 pub struct CharacterControllerPlugin;
 
+/// The system set containing the per-tick character controller systems,
+/// so the app can decide when they run.  They run in `FixedUpdate`, like
+/// the physics they feed.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CharacterControllerSet;
+
 impl Plugin for CharacterControllerPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
-            Update,
+            FixedUpdate,
             (
                 update_grounded,
                 apply_deferred,
                 apply_gravity,
+                apply_knockback,
                 apply_movement_damping,
             )
-                .chain(),
+                .chain()
+                .in_set(CharacterControllerSet),
         )
         .add_systems(
             // Run collision handling in substep schedule
@@ -34,6 +42,7 @@ pub struct Grounded;
 
 /// The acceleration used for character movement.
 #[derive(Component)]
+#[allow(dead_code)]
 pub struct MovementAcceleration(Scalar);
 
 /// The damping factor used for slowing down movement.
@@ -42,12 +51,18 @@ pub struct MovementDampingFactor(Scalar);
 
 /// The strength of a jump.
 #[derive(Component)]
+#[allow(dead_code)]
 pub struct JumpImpulse(Scalar);
 
 /// The gravitational acceleration used for a character controller.
 #[derive(Component)]
 pub struct ControllerGravity(Vector);
 
+/// A velocity added on top of the character's own movement, used to shove
+/// it away from whatever hit it.  Decays with the [`MovementDampingFactor`].
+#[derive(Component, Default)]
+pub struct Knockback(pub Vector);
+
 /// The maximum angle a slope can have for a character controller
 /// to be able to climb and jump. If the slope is steeper than this angle,
 /// the character will slide down.
@@ -63,6 +78,7 @@ pub struct CharacterControllerBundle {
     collider: Collider,
     ground_caster: ShapeCaster,
     gravity: ControllerGravity,
+    knockback: Knockback,
     movement: MovementBundle,
 }
 
@@ -115,10 +131,12 @@ impl CharacterControllerBundle {
             )
             .with_max_time_of_impact(0.2),
             gravity: ControllerGravity(gravity),
+            knockback: Knockback::default(),
             movement: MovementBundle::default(),
         }
     }
 
+    #[allow(dead_code)]
     pub fn with_movement(
         mut self,
         acceleration: Scalar,
@@ -172,6 +190,16 @@ fn apply_gravity(
     }
 }
 
+/// Adds [`Knockback`] to the character's velocity and lets it die down.
+fn apply_knockback(
+    mut query: Query<(&mut Knockback, &MovementDampingFactor, &mut LinearVelocity)>,
+) {
+    for (mut knockback, damping_factor, mut linear_velocity) in &mut query {
+        linear_velocity.0 += knockback.0;
+        knockback.0 *= damping_factor.0;
+    }
+}
+
 /// Slows down movement in the XZ plane.
 fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &mut LinearVelocity)>) {
     for (damping_factor, mut linear_velocity) in &mut query {
@@ -0,0 +1,30 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Where an entity's [`Intent`] comes from.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Controller {
+    /// A local keyboard or gamepad, read through leafwing's `ActionState`.
+    Input,
+    /// The enemy AI.
+    Ai,
+    /// Played back from a recording.
+    Replay,
+}
+
+/// What an entity wants to do this tick.  Controllers fill it in, and the
+/// movement and shooting systems act on it without caring who is in control.
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Intent {
+    /// Direction to move in on the ground, scaled by the fraction of full
+    /// speed to move at.
+    pub movement: Vec3,
+    /// Direction to face, if it should turn.
+    pub aim: Option<Vec3>,
+    /// Shoot.  A player's press stays set until a tick takes the shot.
+    pub fire: bool,
+}
+
+/// Systems that write [`Intent`]s.  Anything acting on them runs after.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ControllerSet;
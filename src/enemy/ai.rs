@@ -0,0 +1,223 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use super::{line_of_sight, Dying, Enemy};
+use crate::level::{MazeGrid, MazeLayout, Wall};
+use crate::player::{nearest_player, Player};
+
+/// Enemies notice the player this close even through walls.
+const SENSE_RADIUS: f32 = 8.0;
+/// How far enemies can see down an open corridor.
+const SIGHT_RANGE: f32 = 30.0;
+/// Seconds an enemy hesitates after noticing the player before it chases.
+const ALERT_TIME: f32 = 0.5;
+/// Seconds spent looking around the last known position before giving up.
+const SEARCH_TIME: f32 = 6.0;
+/// Longest an idle enemy stands still before patrolling again.
+const IDLE_TIME: f32 = 3.0;
+/// Gives up on a patrol goal it can't reach after this many seconds.
+const PATROL_TIMEOUT: f32 = 10.0;
+/// How many cells a patrol wanders from where it starts.
+const PATROL_STEPS: usize = 4;
+/// Fraction of its full speed an enemy walks at while patrolling.
+const PATROL_SPEED: f32 = 0.4;
+const ARRIVE_DISTANCE: f32 = 1.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AiState {
+    /// Standing still.
+    Idle,
+    /// Wandering to a nearby cell.
+    Patrol,
+    /// Has just noticed the player.
+    Alert,
+    /// Following the navmesh to the player.
+    Chase,
+    /// Lost the player and is looking around where it was last seen.
+    Search,
+}
+
+impl AiState {
+    /// Colour used for this state in the debug overlay.
+    pub fn debug_color(&self) -> Color {
+        match self {
+            AiState::Idle => Color::GRAY,
+            AiState::Patrol => Color::GREEN,
+            AiState::Alert => Color::YELLOW,
+            AiState::Chase => Color::RED,
+            AiState::Search => Color::ORANGE,
+        }
+    }
+}
+
+/// What an enemy is doing and what it knows about the nearest player.
+#[derive(Component)]
+pub struct Ai {
+    pub state: AiState,
+    /// When the current state was entered.
+    pub since: f32,
+    /// Where it is walking to while patrolling or searching.
+    pub goal: Vec3,
+    /// Where the player was the last time it was sensed.
+    last_seen: Option<Vec3>,
+    idle_for: f32,
+}
+
+impl Ai {
+    pub fn new(now: f32) -> Self {
+        Ai {
+            state: AiState::Idle,
+            since: now,
+            goal: Vec3::ZERO,
+            last_seen: None,
+            idle_for: 0.0,
+        }
+    }
+
+    fn enter(&mut self, state: AiState, now: f32) {
+        self.state = state;
+        self.since = now;
+    }
+
+    /// Where the enemy should be walking, if anywhere.
+    pub fn destination(&self, player: Vec3) -> Option<Vec3> {
+        match self.state {
+            AiState::Chase => Some(player),
+            AiState::Patrol | AiState::Search => Some(self.goal),
+            AiState::Idle | AiState::Alert => None,
+        }
+    }
+
+    /// Fraction of the enemy's speed to move at.
+    pub fn speed_factor(&self) -> f32 {
+        match self.state {
+            AiState::Patrol => PATROL_SPEED,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ShowAiDebug(pub bool);
+
+/// Where enemies' random choices come from.  Each level seeds it from the
+/// level seed, so a replay of the level makes the same choices.
+#[derive(Resource)]
+pub struct AiRng(pub StdRng);
+
+impl Default for AiRng {
+    fn default() -> Self {
+        AiRng(StdRng::seed_from_u64(0))
+    }
+}
+
+/// Moves every enemy's [`Ai`] between states based on whether it can sense
+/// the nearest player.
+#[allow(clippy::too_many_arguments)]
+pub fn update_ai(
+    time: Res<Time>,
+    spatial_query: SpatialQuery,
+    walls: Query<(), With<Wall>>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Option<Res<MazeLayout>>,
+    grid: Option<Res<MazeGrid>>,
+    mut enemy_query: Query<(&mut Ai, &Transform), Without<Dying>>,
+    mut rng: ResMut<AiRng>,
+) {
+    let players: Vec<Vec3> = player_query.iter().map(|xform| xform.translation).collect();
+    let now = time.elapsed_seconds();
+    let rng = &mut rng.0;
+    let wander = |from: Vec3, rng: &mut StdRng| match (&maze, &grid) {
+        (Some(maze), Some(grid)) => patrol_goal(maze, grid, from, rng),
+        _ => from,
+    };
+    for (mut ai, xform) in enemy_query.iter_mut() {
+        let pos = xform.translation;
+        let sensed = nearest_player(&players, pos).filter(|&player_pos| {
+            let distance = pos.distance(player_pos);
+            distance <= SENSE_RADIUS
+                || (distance <= SIGHT_RANGE
+                    && line_of_sight(&spatial_query, &walls, pos, player_pos))
+        });
+        let senses = sensed.is_some();
+        if senses {
+            ai.last_seen = sensed;
+        }
+        let elapsed = now - ai.since;
+        match ai.state {
+            AiState::Idle | AiState::Patrol if senses => ai.enter(AiState::Alert, now),
+            AiState::Idle if elapsed > ai.idle_for => {
+                ai.goal = wander(pos, rng);
+                ai.enter(AiState::Patrol, now);
+            }
+            AiState::Patrol if arrived(pos, ai.goal) || elapsed > PATROL_TIMEOUT => {
+                ai.idle_for = rng.gen_range(0.0..IDLE_TIME);
+                ai.enter(AiState::Idle, now);
+            }
+            AiState::Alert | AiState::Chase if !senses => {
+                ai.goal = ai.last_seen.unwrap_or(pos);
+                ai.enter(AiState::Search, now);
+            }
+            AiState::Alert if elapsed > ALERT_TIME => ai.enter(AiState::Chase, now),
+            AiState::Search if senses => ai.enter(AiState::Chase, now),
+            AiState::Search if elapsed > SEARCH_TIME => {
+                ai.last_seen = None;
+                ai.idle_for = rng.gen_range(0.0..IDLE_TIME);
+                ai.enter(AiState::Idle, now);
+            }
+            // Reached the last known position; poke around nearby.
+            AiState::Search if arrived(pos, ai.goal) => ai.goal = wander(pos, rng),
+            _ => {}
+        }
+    }
+}
+
+fn arrived(pos: Vec3, goal: Vec3) -> bool {
+    Vec2::new(pos.x - goal.x, pos.z - goal.z).length() < ARRIVE_DISTANCE
+}
+
+/// The centre of a cell a short random walk through the maze from `from`.
+fn patrol_goal(maze: &MazeLayout, grid: &MazeGrid, from: Vec3, rng: &mut impl Rng) -> Vec3 {
+    let Some(mut cell) = grid.cell_at(from) else {
+        return from;
+    };
+    for _ in 0..PATROL_STEPS {
+        match maze.open_neighbours(cell).choose(rng) {
+            Some(&next) => cell = next,
+            None => break,
+        }
+    }
+    grid.cell_center(cell, from.y)
+}
+
+/// Draws a ring over each enemy coloured by its [`AiState`], its sensing
+/// radius, a line to wherever it is heading and the navmesh path it is
+/// following.
+pub fn draw_ai_debug(
+    show: Res<ShowAiDebug>,
+    mut gizmos: Gizmos,
+    player_query: Query<&Transform, With<Player>>,
+    enemy_query: Query<(&Ai, &Enemy, &Transform), Without<Dying>>,
+) {
+    if !show.0 {
+        return;
+    }
+    let players: Vec<Vec3> = player_query.iter().map(|xform| xform.translation).collect();
+    for (ai, enemy, xform) in enemy_query.iter() {
+        let pos = xform.translation;
+        let player_pos = nearest_player(&players, pos).unwrap_or(pos);
+        let color = ai.state.debug_color();
+        gizmos.circle(pos + Vec3::Y * 2.0, Vec3::Y, 0.75, color);
+        gizmos.circle(pos, Vec3::Y, SENSE_RADIUS, color.with_a(0.2));
+        if let Some(destination) = ai.destination(player_pos) {
+            gizmos.line(pos, destination, color);
+        }
+        if matches!(ai.state, AiState::Patrol | AiState::Search) {
+            let path = enemy.path.points().iter().cloned();
+            gizmos.linestrip(std::iter::once(pos).chain(path), Color::CYAN);
+        }
+    }
+}
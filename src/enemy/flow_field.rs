@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+
+use crate::level::{Cell, MazeGrid, MazeLayout};
+use crate::player::Player;
+
+/// Steps from every maze cell to the closest player's cell.  Every chasing
+/// enemy reads its next move from here instead of asking the navmesh for a
+/// path of its own.
+#[derive(Resource, Default)]
+pub struct FlowField {
+    targets: Vec<Cell>,
+    distances: Vec<Option<usize>>,
+}
+
+impl FlowField {
+    /// Where an enemy at `position` should head to reach `target`: the
+    /// centre of the open neighbouring cell closest to it, or `target`
+    /// itself once in the same cell.  `None` if either is outside the maze.
+    pub fn next_waypoint(
+        &self,
+        maze: &MazeLayout,
+        grid: &MazeGrid,
+        position: Vec3,
+        target: Vec3,
+    ) -> Option<Vec3> {
+        let cell = grid.cell_at(position)?;
+        match (*self.distances.get(maze.index(cell))?)? {
+            0 => Some(target),
+            _ => maze
+                .open_neighbours(cell)
+                .into_iter()
+                .filter_map(|next| self.distances[maze.index(next)].map(|steps| (steps, next)))
+                .min()
+                .map(|(_, next)| grid.cell_center(next, position.y)),
+        }
+    }
+}
+
+/// Rebuilds the [`FlowField`] when a player moves to another cell or a new
+/// maze is built.
+pub fn update_flow_field(
+    maze: Option<Res<MazeLayout>>,
+    grid: Option<Res<MazeGrid>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut field: ResMut<FlowField>,
+) {
+    let (Some(maze), Some(grid)) = (maze, grid) else {
+        return;
+    };
+    let targets: Vec<Cell> = player_query
+        .iter()
+        .filter_map(|xform| grid.cell_at(xform.translation))
+        .collect();
+    if targets == field.targets && !maze.is_changed() {
+        return;
+    }
+    field.distances = if targets.is_empty() {
+        Vec::new()
+    } else {
+        maze.distances_from_any(&targets)
+    };
+    field.targets = targets;
+}
@@ -0,0 +1,74 @@
+use bevy::asset::LoadedFolder;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// How an enemy goes after the player.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Behaviour {
+    /// Follows the navmesh straight to the player and hurts it on contact.
+    #[default]
+    Chase,
+    /// Closes in until it can see the player from `standoff` away, then
+    /// holds position and shoots every `fire_period` seconds.
+    Ranged { standoff: f32, fire_period: f32 },
+}
+
+/// One type of enemy, read from a `.enemy.ron` file in `assets/enemies`.
+///
+/// The [`Default`] is the original bug, used when a level asks for a kind
+/// that hasn't loaded.
+#[derive(Asset, TypePath, Deserialize, Clone, Debug)]
+pub struct EnemyKind {
+    /// The name level configs use in their enemy tables.
+    pub name: String,
+    /// glTF file with the enemy's scene and animations.
+    pub model: String,
+    pub collider_radius: f32,
+    pub speed: f32,
+    pub health: i32,
+    /// Damage done to the player on contact.
+    pub damage: i32,
+    /// Seconds between asking the navmesh for a new path.
+    pub path_period: f32,
+    #[serde(default)]
+    pub behaviour: Behaviour,
+}
+
+impl Default for EnemyKind {
+    fn default() -> Self {
+        EnemyKind {
+            name: String::from("bug"),
+            model: String::from("Bug.glb"),
+            collider_radius: 0.75,
+            speed: 10.0,
+            health: 2,
+            damage: 1,
+            path_period: 0.4,
+            behaviour: Behaviour::Chase,
+        }
+    }
+}
+
+/// Keeps every enemy kind in `assets/enemies` loaded.
+#[derive(Resource)]
+pub struct EnemyKindsFolder(pub Handle<LoadedFolder>);
+
+pub fn load_enemy_kinds(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(EnemyKindsFolder(assets.load_folder("enemies")));
+}
+
+/// The loaded enemy kinds, looked up by name.
+#[derive(SystemParam)]
+pub struct EnemyKinds<'w> {
+    kinds: Res<'w, Assets<EnemyKind>>,
+}
+
+impl<'w> EnemyKinds<'w> {
+    pub fn get(&self, name: &str) -> Option<&EnemyKind> {
+        self.kinds
+            .iter()
+            .map(|(_, kind)| kind)
+            .find(|kind| kind.name == name)
+    }
+}
@@ -0,0 +1,306 @@
+use crate::character_controller as cc;
+use crate::controller::{Controller, Intent};
+use crate::health::Health;
+use crate::interpolation::Interpolated;
+use crate::level::{LevelEntity, MazeGrid, MazeLayout};
+use crate::player;
+use bevy::gltf::Gltf;
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+use oxidized_navigation::query::find_path;
+use oxidized_navigation::NavMesh;
+use oxidized_navigation::NavMeshSettings;
+
+mod ai;
+mod flow_field;
+mod kind;
+mod path;
+mod ranged;
+mod separation;
+pub use ai::{draw_ai_debug, update_ai, Ai, AiRng, AiState, ShowAiDebug};
+pub use flow_field::{update_flow_field, FlowField};
+pub use kind::{load_enemy_kinds, Behaviour, EnemyKind, EnemyKinds, EnemyKindsFolder};
+pub use path::NavPath;
+pub use ranged::{fire_at_player, line_of_sight, take_aim, RangedAttack};
+pub use separation::{separate_enemies, Separation};
+
+const ENEMY_FLASH_TIME: f32 = 0.15;
+const ENEMY_DEATH_TIME: f32 = 1.0;
+/// How quickly an enemy's velocity turns towards where it wants to go.
+const ENEMY_STEERING: f32 = 8.0;
+
+#[derive(Resource)]
+pub struct EnemyCounts {
+    pub count: i32,
+    pub killed: i32,
+}
+
+#[derive(Component)]
+pub struct Enemy {
+    current_path: Vec3,
+    pub path: NavPath,
+    pub last_hit_time: Option<f32>,
+    pub kind: EnemyKind,
+    /// The glTF `kind.model` came from, for its animations.
+    model: Handle<Gltf>,
+}
+
+/// Added to an enemy when its health runs out.  It stops moving and
+/// colliding, plays its death animation and is despawned after
+/// `ENEMY_DEATH_TIME` seconds.
+#[derive(Component)]
+pub struct Dying {
+    since: f32,
+}
+
+#[derive(Resource, Clone)]
+pub struct WalkAnimation(pub Handle<AnimationClip>);
+
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    assets: &ResMut<AssetServer>,
+    now: f32,
+    kind: &EnemyKind,
+    x: f32,
+    y: f32,
+) {
+    let transform = Transform::from_xyz(x, 1.5, y);
+    let mut enemy = commands.spawn(SceneBundle {
+        scene: assets.load(format!("{}#Scene0", kind.model)),
+        transform,
+        ..Default::default()
+    });
+    enemy
+        .insert(Interpolated::new(transform))
+        .insert(Controller::Ai)
+        .insert(Intent::default())
+        .insert(Enemy {
+            current_path: Vec3::new(0.0, 0.0, 0.0),
+            path: NavPath::default(),
+            last_hit_time: None,
+            kind: kind.clone(),
+            model: assets.load(kind.model.clone()),
+        })
+        .insert(Ai::new(now))
+        .insert(Separation::default())
+        .insert(Health::new(kind.health))
+        .insert(LevelEntity)
+        .insert(LinearVelocity(Vec3::new(0.0, 0.0, 0.0)))
+        //.insert(DrawPath {
+        //    color: Color::Rgba {
+        //        red: 0.0,
+        //        green: 0.0,
+        //        blue: 1.0,
+        //        alpha: 1.0,
+        //    },
+        //    pulled_path: vec![],
+        //    timer: None,
+        //})
+        .insert(cc::CharacterControllerBundle::new(
+            Collider::ball(kind.collider_radius),
+            Vec3::new(0.0, -1.0, 0.0),
+        ));
+    if let Behaviour::Ranged { .. } = kind.behaviour {
+        enemy.insert(RangedAttack::default());
+    }
+}
+
+pub fn setup_walk_animation(mut commands: Commands, assets: ResMut<AssetServer>) {
+    let walk_animation = WalkAnimation(assets.load("Bug.glb#Animation0"));
+    commands.insert_resource(walk_animation);
+}
+/// Starts the walk cycle when a model's scene is spawned.  Enemies use the
+/// first animation in their own kind's model.
+pub fn start_walk_animation(
+    animation: Res<WalkAnimation>,
+    gltfs: Res<Assets<Gltf>>,
+    enemies: Query<&Enemy>,
+    parents: Query<&Parent>,
+    mut players: Query<(Entity, &mut AnimationPlayer), Added<AnimationPlayer>>,
+) {
+    for (entity, mut player) in &mut players.iter_mut() {
+        let clip = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| enemies.get(ancestor).ok())
+            .and_then(|enemy| gltfs.get(&enemy.model))
+            .and_then(|gltf| gltf.animations.first())
+            .unwrap_or(&animation.0);
+        player.play(clip.clone_weak()).repeat();
+    }
+}
+
+pub fn kill_enemy(commands: &mut Commands, enemy: Entity, time: &Time) {
+    commands
+        .entity(enemy)
+        .insert(Dying {
+            since: time.elapsed_seconds(),
+        })
+        .remove::<(Collider, RigidBody)>();
+}
+
+/// Plays the "Death" clip from the enemy's model on newly killed enemies,
+/// or just freezes the walk cycle if the model doesn't have one.
+pub fn start_death_animation(
+    dying: Query<(Entity, &Enemy), Added<Dying>>,
+    gltfs: Res<Assets<Gltf>>,
+    children: Query<&Children>,
+    mut players: Query<&mut AnimationPlayer>,
+) {
+    for (enemy, Enemy { model, .. }) in dying.iter() {
+        let death_clip = gltfs
+            .get(model)
+            .and_then(|gltf| gltf.named_animations.get("Death"));
+        for descendant in children.iter_descendants(enemy) {
+            if let Ok(mut player) = players.get_mut(descendant) {
+                match death_clip {
+                    Some(clip) => {
+                        player.play(clip.clone_weak());
+                    }
+                    None => {
+                        player.pause();
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn despawn_dead_enemies(
+    mut commands: Commands,
+    time: Res<Time>,
+    query: Query<(Entity, &Dying)>,
+) {
+    for (entity, dying) in query.iter() {
+        if time.elapsed_seconds() - dying.since > ENEMY_DEATH_TIME {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Blinks an enemy briefly after it takes a hit it survives.
+pub fn flash_enemies(time: Res<Time>, mut query: Query<(&Enemy, &mut Visibility)>) {
+    let elapsed = time.elapsed_seconds();
+    for (enemy, mut visibility) in query.iter_mut() {
+        *visibility = match enemy.last_hit_time {
+            Some(hit) if elapsed - hit < ENEMY_FLASH_TIME => Visibility::Hidden,
+            _ => Visibility::Inherited,
+        };
+    }
+}
+
+/// Decides where each enemy wants to go and which way it should face, and
+/// writes that into its [`Intent`].
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn steer_enemy(
+    player_query: Query<&Transform, (With<player::Player>, Without<Enemy>)>,
+    mut enemy_query: Query<
+        (
+            &mut Enemy,
+            &mut Intent,
+            &Transform,
+            &Ai,
+            &Separation,
+            Option<&RangedAttack>,
+        ),
+        (Without<player::Player>, Without<Dying>),
+    >,
+    navigation: Option<Res<NavMesh>>,
+    time: Res<Time>,
+    settings: Option<Res<NavMeshSettings>>,
+    field: Res<FlowField>,
+    maze: Option<Res<MazeLayout>>,
+    grid: Option<Res<MazeGrid>>,
+) {
+    let players: Vec<Vec3> = player_query.iter().map(|xform| xform.translation).collect();
+    let now = time.elapsed_seconds();
+    let binding = navigation.map(|navigation| navigation.get());
+    let tileset = binding.as_ref().map(|binding| binding.try_read());
+    for (mut enemy, mut intent, enemy_xform, ai, separation, attack) in enemy_query.iter_mut() {
+        let enemy_pos = enemy_xform.translation;
+        // Each enemy goes after whichever player is closest to it.
+        let player_pos = player::nearest_player(&players, enemy_pos).unwrap_or(enemy_pos);
+        let destination = ai.destination(player_pos);
+        // Chasers all share the flow field; only enemies headed somewhere
+        // else need a navmesh path of their own.
+        let flow = match (ai.state, &maze, &grid) {
+            (AiState::Chase, Some(maze), Some(grid)) => {
+                field.next_waypoint(maze, grid, enemy_pos, player_pos)
+            }
+            _ => None,
+        };
+        let steer_at = if attack.is_some_and(|attack| attack.in_position) {
+            None
+        } else if let Some(waypoint) = flow {
+            Some((waypoint, 1.0))
+        } else if let Some(target) = destination {
+            let throttled = enemy
+                .path
+                .queried_at
+                .is_some_and(|at| now - at < enemy.kind.path_period);
+            if enemy.path.is_stale(enemy_pos, target) && !throttled {
+                let points = match (&tileset, &settings) {
+                    (Some(Ok(tiles)), Some(settings)) => {
+                        match find_path(tiles, settings, enemy_pos, target, None, None) {
+                            Ok(path) => path,
+                            Err(e) => {
+                                warn!("Path error: {:?}", e);
+                                vec![target]
+                            }
+                        }
+                    }
+                    (Some(Err(e)), _) => {
+                        warn!("Navigation tiles error: {:?}", e);
+                        vec![target]
+                    }
+                    // No level, so no navmesh yet.
+                    _ => vec![target],
+                };
+                enemy.path.set(points, target, now);
+            }
+            enemy.path.steer_from(enemy_pos)
+        } else {
+            None
+        };
+        let desired = match steer_at {
+            Some((point, speed)) => {
+                let mut offset = point - enemy_pos;
+                offset.y = 0.0;
+                offset.normalize_or_zero() * ai.speed_factor() * speed
+            }
+            None => Vec3::ZERO,
+        };
+        // Keep clear of other enemies while heading where it wants to go.
+        let separation = separation.0 / enemy.kind.speed;
+        intent.movement = (desired + separation).clamp_length_max(1.0);
+        if desired != Vec3::ZERO {
+            enemy.current_path = desired.normalize();
+        } else if ai.state != AiState::Idle {
+            // Standing still, but the player has been spotted: face it.
+            let mut facing = player_pos - enemy_pos;
+            facing.y = 0.0;
+            enemy.current_path = facing.normalize_or_zero();
+        }
+        intent.aim = Some(enemy.current_path);
+    }
+}
+
+/// Moves and turns enemies as their [`Intent`] asks.
+pub fn move_enemy(
+    time: Res<Time>,
+    mut enemy_query: Query<(&Enemy, &Intent, &mut LinearVelocity, &mut Transform), Without<Dying>>,
+) {
+    for (enemy, intent, mut velocity, mut enemy_xform) in enemy_query.iter_mut() {
+        // Turn towards the desired velocity over a few frames instead of
+        // snapping to it.
+        let steering = intent.movement * enemy.kind.speed;
+        let blend = (ENEMY_STEERING * time.delta_seconds()).min(1.0);
+        velocity.x += (steering.x - velocity.x) * blend;
+        velocity.z += (steering.z - velocity.z) * blend;
+        if let Some(heading) = intent.aim {
+            if heading.x.abs() > 0.0 {
+                let heading_angle = heading.x.atan2(heading.z);
+                enemy_xform.rotation = Quat::from_rotation_y(heading_angle);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+
+/// Within this distance of a waypoint it counts as reached.
+const WAYPOINT_RADIUS: f32 = 1.0;
+/// How far ahead of a corner an enemy starts turning towards the next leg.
+const LOOKAHEAD: f32 = 2.5;
+/// Slows down over this distance when coming up to the end of a path.
+const ARRIVAL_RADIUS: f32 = 3.0;
+/// The target has to move this far before a new path is worth asking for.
+const REPATH_DISTANCE: f32 = 2.0;
+/// A path is abandoned once the enemy has been pushed this far off it.
+const OFF_PATH_DISTANCE: f32 = 3.0;
+
+/// A pulled navmesh path being followed one waypoint at a time.
+#[derive(Default)]
+pub struct NavPath {
+    points: Vec<Vec3>,
+    next: usize,
+    /// The position the path was found to.
+    target: Option<Vec3>,
+    /// When the path was last asked for.
+    pub queried_at: Option<f32>,
+}
+
+impl NavPath {
+    pub fn set(&mut self, points: Vec<Vec3>, target: Vec3, now: f32) {
+        self.points = points;
+        self.next = 0;
+        self.target = Some(target);
+        self.queried_at = Some(now);
+    }
+
+    pub fn points(&self) -> &[Vec3] {
+        &self.points[self.next.min(self.points.len())..]
+    }
+
+    /// Whether a new path is needed to get from `position` to `target`:
+    /// there is none, the target has moved, or the enemy has been knocked
+    /// away from the one it has.
+    pub fn is_stale(&self, position: Vec3, target: Vec3) -> bool {
+        let (Some(old_target), Some(&next)) = (self.target, self.points.get(self.next)) else {
+            return true;
+        };
+        let previous = self.points[self.next.saturating_sub(1)];
+        flat_distance(old_target, target) > REPATH_DISTANCE
+            || distance_to_segment(position, previous, next) > OFF_PATH_DISTANCE
+    }
+
+    /// Advances past reached waypoints and returns the point to steer at
+    /// and how fast to go there, as a fraction of full speed.
+    pub fn steer_from(&mut self, position: Vec3) -> Option<(Vec3, f32)> {
+        while self.next + 1 < self.points.len()
+            && flat_distance(position, self.points[self.next]) < WAYPOINT_RADIUS
+        {
+            self.next += 1;
+        }
+        let waypoint = *self.points.get(self.next)?;
+        let distance = flat_distance(position, waypoint);
+        match self.points.get(self.next + 1) {
+            // Start turning into the next leg before reaching the corner,
+            // rather than running into it and then turning.
+            Some(&after) if distance < LOOKAHEAD => {
+                Some((waypoint.lerp(after, 1.0 - distance / LOOKAHEAD), 1.0))
+            }
+            Some(_) => Some((waypoint, 1.0)),
+            None => Some((waypoint, (distance / ARRIVAL_RADIUS).min(1.0))),
+        }
+    }
+}
+
+fn flat_distance(a: Vec3, b: Vec3) -> f32 {
+    a.xz().distance(b.xz())
+}
+
+/// Distance on the ground from `point` to the segment from `a` to `b`.
+fn distance_to_segment(point: Vec3, a: Vec3, b: Vec3) -> f32 {
+    let (point, a, b) = (point.xz(), a.xz(), b.xz());
+    let segment = b - a;
+    let along = if segment.length_squared() > 0.0 {
+        ((point - a).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance(a + segment * along)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORNER: Vec3 = Vec3::new(5.0, 0.0, 0.0);
+    const END: Vec3 = Vec3::new(5.0, 0.0, 10.0);
+
+    /// Five units along X from the origin, then ten along Z.
+    fn round_the_corner() -> NavPath {
+        let mut path = NavPath::default();
+        path.set(vec![Vec3::ZERO, CORNER, END], END, 0.0);
+        path
+    }
+
+    #[test]
+    fn steering_cuts_the_corner_and_slows_at_the_end() {
+        let mut path = round_the_corner();
+        assert_eq!(path.steer_from(Vec3::ZERO), Some((CORNER, 1.0)));
+        // Coming up to the corner, it turns into the next leg early.
+        let (point, speed) = path.steer_from(Vec3::new(3.5, 0.0, 0.0)).unwrap();
+        assert!(point.distance(Vec3::new(5.0, 0.0, 4.0)) < 1e-5);
+        assert_eq!(speed, 1.0);
+        // Once round it heads for the end...
+        assert_eq!(path.steer_from(Vec3::new(4.5, 0.0, 0.5)), Some((END, 1.0)));
+        assert_eq!(path.points(), &[END]);
+        // ...and slows down as it gets there.
+        let (point, speed) = path.steer_from(Vec3::new(5.0, 0.0, 8.5)).unwrap();
+        assert_eq!(point, END);
+        assert!((speed - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn an_empty_path_goes_nowhere() {
+        assert_eq!(NavPath::default().steer_from(Vec3::ZERO), None);
+    }
+
+    #[test]
+    fn paths_go_stale_when_the_target_moves_or_the_enemy_is_pushed_off() {
+        assert!(NavPath::default().is_stale(Vec3::ZERO, END));
+        let mut path = round_the_corner();
+        path.steer_from(Vec3::ZERO);
+        let position = Vec3::new(2.0, 0.0, 0.5);
+        assert!(!path.is_stale(position, END));
+        assert!(!path.is_stale(position, END + Vec3::X * 1.5));
+        assert!(path.is_stale(position, END + Vec3::X * 2.5));
+        assert!(path.is_stale(Vec3::new(2.0, 0.0, 4.0), END));
+    }
+}
@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+
+use super::{Ai, AiState, Behaviour, Dying, Enemy};
+use crate::bullet::{self, Team};
+use crate::controller::Intent;
+use crate::level::Wall;
+use crate::player::{nearest_player, Player};
+
+/// Shooting state for enemies with [`Behaviour::Ranged`].
+#[derive(Component, Default)]
+pub struct RangedAttack {
+    last_shot_time: Option<f32>,
+    /// Whether the player was in range and in sight on the last check.
+    pub in_position: bool,
+}
+
+/// True if no wall stands between `from` and `to`.
+pub fn line_of_sight(
+    spatial_query: &SpatialQuery,
+    walls: &Query<(), With<Wall>>,
+    from: Vec3,
+    to: Vec3,
+) -> bool {
+    let offset = to - from;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return true;
+    }
+    let mut clear = true;
+    spatial_query.ray_hits_callback(
+        from,
+        offset / distance,
+        distance,
+        true,
+        SpatialQueryFilter::default(),
+        |hit| {
+            if walls.contains(hit.entity) {
+                clear = false;
+            }
+            clear
+        },
+    );
+    clear
+}
+
+/// Checks which chasing ranged enemies can see the nearest player from their
+/// standoff distance, and has those want to fire.
+pub fn take_aim(
+    spatial_query: SpatialQuery,
+    walls: Query<(), With<Wall>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut enemy_query: Query<
+        (&Enemy, &Ai, &mut RangedAttack, &mut Intent, &Transform),
+        Without<Dying>,
+    >,
+) {
+    let players: Vec<Vec3> = player_query.iter().map(|xform| xform.translation).collect();
+    for (enemy, ai, mut attack, mut intent, xform) in enemy_query.iter_mut() {
+        let Behaviour::Ranged { standoff, .. } = enemy.kind.behaviour else {
+            continue;
+        };
+        let Some(player_pos) = nearest_player(&players, xform.translation) else {
+            attack.in_position = false;
+            intent.fire = false;
+            continue;
+        };
+        let mut aim = player_pos - xform.translation;
+        aim.y = 0.0;
+        attack.in_position = ai.state == AiState::Chase
+            && aim.length() <= standoff
+            && line_of_sight(&spatial_query, &walls, xform.translation, player_pos);
+        intent.fire = attack.in_position;
+    }
+}
+
+/// Shoots for ranged enemies whose [`Intent`] is to fire, as often as their
+/// kind allows, in the direction they are aiming.
+pub fn fire_at_player(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    time: Res<Time>,
+    mut enemy_query: Query<
+        (Entity, &Enemy, &Intent, &mut RangedAttack, &Transform),
+        Without<Dying>,
+    >,
+) {
+    let elapsed = time.elapsed_seconds();
+    for (entity, enemy, intent, mut attack, xform) in enemy_query.iter_mut() {
+        let Behaviour::Ranged { fire_period, .. } = enemy.kind.behaviour else {
+            continue;
+        };
+        let Some(aim) = intent.aim.filter(|_| intent.fire) else {
+            continue;
+        };
+        if attack
+            .last_shot_time
+            .is_none_or(|shot| elapsed - shot > fire_period)
+        {
+            attack.last_shot_time = Some(elapsed);
+            bullet::spawn_bullet(
+                &mut commands,
+                &assets,
+                &time,
+                entity,
+                Team::Enemies,
+                aim.normalize_or_zero(),
+                xform,
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use super::Dying;
+
+/// Enemies closer together than this push each other apart.
+const SEPARATION_RADIUS: f32 = 2.5;
+/// Speed of the push between two enemies standing on top of each other.
+/// It falls off to nothing at `SEPARATION_RADIUS`.
+const SEPARATION_SPEED: f32 = 6.0;
+
+/// The velocity pushing an enemy away from its neighbours, added to where
+/// it is steering so crowds spread out instead of queueing on one line.
+#[derive(Component, Default)]
+pub struct Separation(pub Vec3);
+
+/// Buckets positions into square cells on the ground so that nearby
+/// points can be found without checking every pair.
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(Entity, Vec3)>>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash {
+            cell_size,
+            cells: HashMap::default(),
+        }
+    }
+
+    fn key(&self, position: Vec3) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.z / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec3) {
+        let key = self.key(position);
+        self.cells.entry(key).or_default().push((entity, position));
+    }
+
+    /// Everything in the 3x3 block of cells around `position`, which covers
+    /// at least `cell_size` in every direction.
+    pub fn nearby(&self, position: Vec3) -> impl Iterator<Item = &(Entity, Vec3)> {
+        let (x, z) = self.key(position);
+        (x - 1..=x + 1)
+            .flat_map(move |x| (z - 1..=z + 1).map(move |z| (x, z)))
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+    }
+}
+
+pub fn separate_enemies(mut query: Query<(Entity, &Transform, &mut Separation), Without<Dying>>) {
+    let mut hash = SpatialHash::new(SEPARATION_RADIUS);
+    for (entity, xform, _) in query.iter() {
+        hash.insert(entity, xform.translation);
+    }
+    for (entity, xform, mut separation) in query.iter_mut() {
+        let mut push = Vec3::ZERO;
+        for &(other, position) in hash.nearby(xform.translation) {
+            let mut away = xform.translation - position;
+            away.y = 0.0;
+            let distance = away.length();
+            if other != entity && distance > 0.0 && distance < SEPARATION_RADIUS {
+                push += away / distance * (1.0 - distance / SEPARATION_RADIUS);
+            }
+        }
+        separation.0 = push * SEPARATION_SPEED;
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{
+    app::RunFixedUpdateLoop, ecs::schedule::ExecutorKind, prelude::*,
+    time::run_fixed_update_schedule,
+};
+use bevy_xpbd_3d::prelude::*;
+
+use crate::character_controller::{CharacterControllerPlugin, CharacterControllerSet};
+use crate::controller::ControllerSet;
+use crate::state::GameState;
+use crate::{bullet, enemy, input, interpolation, player, stats};
+
+/// Everything that plays out on the fixed timestep while a level is being
+/// played: physics, players, enemies and bullets.  Kept apart from the
+/// window, menus and drawing so it can also be run headless.
+pub struct GameplayPlugin;
+
+/// The gameplay systems run each tick.  Anything else that plays a part in
+/// the game goes in here too, so its place in the tick is fixed.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GameplaySet;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(enemy::EnemyCounts {
+            count: 0,
+            killed: 0,
+        })
+        .init_resource::<stats::RunStats>()
+        .init_resource::<enemy::FlowField>()
+        .init_resource::<enemy::AiRng>()
+        .add_event::<player::PlayerDied>()
+        .add_event::<player::PlayerShot>()
+        .add_plugins((PhysicsPlugins::new(FixedUpdate), CharacterControllerPlugin))
+        // One physics step per tick of `FixedUpdate`'s default 64Hz.
+        .insert_resource(Time::new_with(Physics::fixed_once_hz(64.0)))
+        // Gameplay runs on the fixed timestep so replays play out the same.
+        // Run it on one thread too, so commands are applied in the same
+        // order every time.
+        .edit_schedule(FixedUpdate, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        })
+        // Each tick runs in an order spelled out in full: decisions, then
+        // acting on them, then the character controllers and physics.
+        // Even on one thread, systems left unordered can run in a different
+        // order from one launch to the next, and a replay recorded in one
+        // would go astray in another.
+        .configure_sets(
+            FixedUpdate,
+            (GameplaySet, CharacterControllerSet)
+                .chain()
+                .before(PhysicsSet::Prepare)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                enemy::update_flow_field,
+                enemy::update_ai,
+                enemy::separate_enemies,
+                enemy::take_aim.in_set(ControllerSet),
+                enemy::steer_enemy.in_set(ControllerSet),
+                enemy::move_enemy.after(ControllerSet),
+                enemy::fire_at_player,
+                input::move_player,
+                input::fire_gun,
+                bullet::hit_bullet,
+                bullet::expire_bullets,
+                enemy::despawn_dead_enemies,
+                player::regen_ammo,
+                // Reads `PlayerShot`s in the tick they are sent, so none are
+                // dropped on frames that run several ticks or none.
+                player::hurt_player,
+            )
+                .chain()
+                .in_set(GameplaySet),
+        )
+        // Ticks move things in steps; draw them smoothly in between.
+        .add_systems(
+            RunFixedUpdateLoop,
+            interpolation::restore_transforms.before(run_fixed_update_schedule),
+        )
+        .add_systems(
+            FixedUpdate,
+            interpolation::store_transforms.after(PhysicsSet::Sync),
+        )
+        .add_systems(Update, interpolation::interpolate_transforms);
+    }
+}
+
+/// A windowless app running the gameplay with frames `frame_time` apart.
+/// It starts playing on its first update, with no level built.
+#[cfg(test)]
+pub fn headless_app(frame_time: std::time::Duration) -> App {
+    let mut app = App::new();
+    // Physics and scenes want their assets around, even with nothing to
+    // draw.
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        bevy::scene::ScenePlugin,
+        TransformPlugin,
+        HierarchyPlugin,
+        crate::state::GameStatePlugin,
+        GameplayPlugin,
+    ))
+    .init_asset::<Mesh>()
+    .init_asset::<StandardMaterial>()
+    .init_asset::<bevy::gltf::Gltf>()
+    .insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(frame_time))
+    .insert_resource(NextState(Some(GameState::Playing)))
+    .add_systems(Startup, bullet::setup_impact_effect);
+    app
+}
+
+/// Spawns a keyboard player at `(x, z)` in an app from [`headless_app`].
+#[cfg(test)]
+pub fn spawn_test_player(app: &mut App, x: f32, z: f32) -> Entity {
+    use bevy::ecs::system::RunSystemOnce;
+
+    app.world
+        .run_system_once(move |mut commands: Commands, assets: ResMut<AssetServer>| {
+            player::spawn_player(
+                &mut commands,
+                &assets,
+                x,
+                z,
+                1.0,
+                &crate::bindings::Bindings::default(),
+                0,
+                1,
+                input::InputDevices {
+                    keyboard: true,
+                    gamepad: None,
+                },
+            )
+        })
+}
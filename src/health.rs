@@ -0,0 +1,22 @@
+use bevy::prelude::*;
+
+/// Hit points for anything that can be damaged.
+#[derive(Component)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn damage(&mut self, amount: i32) {
+        self.current = (self.current - amount).max(0);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
+}
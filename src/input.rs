@@ -1,17 +1,18 @@
-use std::f32::consts::PI;
-
-use crate::character_controller as cc;
+use crate::bindings::Bindings;
+use crate::bullet::Team;
+use crate::camera::PlayerCamera;
+use crate::controller::{Controller, Intent};
+use crate::enemy::ShowAiDebug;
 use crate::fps::ShowFps;
 use crate::player;
-use crate::player::Player;
-use bevy::{
-    input::gamepad::{GamepadAxisChangedEvent, GamepadButtonChangedEvent, GamepadButtonInput},
-    prelude::*,
-    time::TimePlugin,
-};
+use crate::replay::Playback;
+use crate::state::GameState;
+use crate::stats::RunStats;
+use bevy::{ecs::system::SystemParam, prelude::*, window::PrimaryWindow};
 use bevy_xpbd_3d::prelude::*;
-use leafwing_input_manager::{prelude::*, user_input::InputKind};
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect)]
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Serialize, Deserialize)]
 pub enum Action {
     MoveLeft,
     MoveRight,
@@ -25,127 +26,283 @@ pub enum Action {
     Aim,
     Move,
     ToggleFps,
+    ToggleAiDebug,
+    Pause,
+}
+
+impl Action {
+    /// The name shown in the controls menu.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::AimLeft => "Aim left",
+            Action::AimRight => "Aim right",
+            Action::AimUp => "Aim up",
+            Action::AimDown => "Aim down",
+            Action::Shoot => "Shoot",
+            Action::Aim => "Aim",
+            Action::Move => "Move",
+            Action::ToggleFps => "Show FPS",
+            Action::ToggleAiDebug => "AI debug view",
+            Action::Pause => "Pause",
+        }
+    }
+}
+
+/// How keyboard players aim, chosen from the pause screen.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AimMode {
+    /// Snapped to four directions with the arrow keys.
+    #[default]
+    Keys,
+    /// Towards the point on the ground under the mouse cursor.
+    Mouse,
 }
 
-pub fn input_bundle() -> InputManagerBundle<Action> {
+impl AimMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            AimMode::Keys => AimMode::Mouse,
+            AimMode::Mouse => AimMode::Keys,
+        }
+    }
+}
+
+/// How many people are playing locally, chosen on the title screen.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct LocalPlayers(pub usize);
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        LocalPlayers(1)
+    }
+}
+
+/// Which devices a local player reads input from.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InputDevices {
+    /// Keyboard and mouse.  Only the first player has them.
+    pub keyboard: bool,
+    /// The player's own gamepad.  `None` for a lone player with no gamepad
+    /// connected yet, who then answers to any gamepad.
+    pub gamepad: Option<Gamepad>,
+}
+
+/// Hands out devices to local players: one gamepad each, with the keyboard
+/// and mouse going to the first player.
+#[derive(SystemParam)]
+pub struct Seats<'w> {
+    players: Res<'w, LocalPlayers>,
+    gamepads: Res<'w, Gamepads>,
+    playback: Option<Res<'w, Playback>>,
+}
+
+impl<'w> Seats<'w> {
+    /// Devices for each player in the next level.  Co-op needs a gamepad per
+    /// player, so there are never more players than gamepads, except in a
+    /// replay where the recording does the playing.
+    pub fn devices(&self) -> Vec<InputDevices> {
+        let gamepads: Vec<Gamepad> = self.gamepads.iter().collect();
+        let count = if self.playback.as_ref().is_some_and(|p| p.is_running()) {
+            self.players.0.max(1)
+        } else {
+            self.players.0.clamp(1, gamepads.len().max(1))
+        };
+        if count < self.players.0 {
+            warn!(
+                "{} players need {} gamepads, only {} connected",
+                self.players.0,
+                self.players.0,
+                gamepads.len()
+            );
+        }
+        (0..count)
+            .map(|i| InputDevices {
+                keyboard: i == 0,
+                gamepad: gamepads.get(i).cloned(),
+            })
+            .collect()
+    }
+}
+
+pub fn input_bundle(bindings: &Bindings, devices: InputDevices) -> InputManagerBundle<Action> {
     InputManagerBundle {
         action_state: ActionState::default(),
         // Describes how to convert from player inputs into those actions
-        input_map: InputMap::new([
-            (InputKind::Keyboard(KeyCode::W), Action::MoveUp),
-            (InputKind::Keyboard(KeyCode::A), Action::MoveLeft),
-            (InputKind::Keyboard(KeyCode::S), Action::MoveDown),
-            (InputKind::Keyboard(KeyCode::D), Action::MoveRight),
-            (InputKind::Keyboard(KeyCode::Left), Action::AimLeft),
-            (InputKind::Keyboard(KeyCode::Right), Action::AimRight),
-            (InputKind::Keyboard(KeyCode::Up), Action::AimUp),
-            (InputKind::Keyboard(KeyCode::Down), Action::AimDown),
-            (InputKind::DualAxis(DualAxis::left_stick()), Action::Move),
-            (InputKind::DualAxis(DualAxis::right_stick()), Action::Aim),
-            (InputKind::Keyboard(KeyCode::Space), Action::Shoot),
-            (
-                InputKind::GamepadButton(GamepadButtonType::RightTrigger),
-                Action::Shoot,
-            ),
-            (InputKind::Keyboard(KeyCode::F), Action::ToggleFps),
-        ]),
+        input_map: bindings.input_map_for(devices),
     }
 }
 pub fn toggle_fps(
     mut show_fps: ResMut<ShowFps>,
-    query: Query<(&ActionState<Action>, &player::Player)>,
+    query: Query<&ActionState<Action>, With<player::Player>>,
+) {
+    if query
+        .iter()
+        .any(|action| action.just_pressed(Action::ToggleFps))
+    {
+        show_fps.0 = !show_fps.0;
+    }
+}
+pub fn toggle_ai_debug(
+    mut show: ResMut<ShowAiDebug>,
+    query: Query<&ActionState<Action>, With<player::Player>>,
+) {
+    if query
+        .iter()
+        .any(|action| action.just_pressed(Action::ToggleAiDebug))
+    {
+        show.0 = !show.0;
+    }
+}
+pub fn toggle_pause(
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    query: Query<&ActionState<Action>, With<player::Player>>,
 ) {
-    if let (action, _) = query.single() {
-        if action.just_pressed(Action::ToggleFps) {
-            show_fps.0 = !show_fps.0;
+    if query
+        .iter()
+        .any(|action| action.just_pressed(Action::Pause))
+    {
+        match state.get() {
+            GameState::Playing => next_state.set(GameState::Paused),
+            GameState::Paused => next_state.set(GameState::Playing),
+            _ => {}
         }
     }
 }
 pub fn fire_gun(
     mut commands: Commands,
     assets: ResMut<AssetServer>,
-    mut query: Query<(&ActionState<Action>, &mut player::Player, &Transform)>,
+    mut query: Query<(Entity, &mut Intent, &mut player::Player, &Transform)>,
+    mut stats: ResMut<RunStats>,
+    time: Res<Time>,
 ) {
-    if let (action, mut player, xform) = query.single_mut() {
-        if action.just_pressed(Action::Shoot) && player.ammunition > 0 {
+    for (entity, mut intent, mut player, xform) in query.iter_mut() {
+        if std::mem::take(&mut intent.fire) && player.ammunition > 0 {
             let shot_direction = Vec3::new(-player.aim.cos(), 0.0, player.aim.sin());
-            crate::bullet::spawn_bullet(&mut commands, assets, shot_direction, xform);
+            crate::bullet::spawn_bullet(
+                &mut commands,
+                &assets,
+                &time,
+                entity,
+                Team::Player,
+                shot_direction,
+                xform,
+            );
             player.ammunition -= 1;
+            stats.shots_fired += 1;
         }
     }
 }
 
-pub fn move_player(
+/// Turns the keyboard, mouse and gamepad state of locally controlled
+/// entities into their [`Intent`].
+pub fn read_input(
+    aim_mode: Res<AimMode>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform, Option<&PlayerCamera>), With<Camera3d>>,
     mut query: Query<(
+        &Controller,
         &ActionState<Action>,
-        &mut LinearVelocity,
-        &mut player::Player,
-        &mut Transform,
+        &mut Intent,
+        &Transform,
+        &InputDevices,
     )>,
-    t: Res<Time>,
 ) {
-    let (action_state, mut player_velocity, mut player, mut xform) = query.single_mut();
-
-    // Each action has a button-like state of its own that you can check
-    let mut xlat = Vec3::new(0.0, 0.0, 0.0);
-    let speed = 300.0;
-    if action_state.pressed(Action::MoveUp) {
-        xlat.z += 1.0;
-    }
-    if action_state.pressed(Action::MoveLeft) {
-        xlat.x += 1.0;
-    }
-    if action_state.pressed(Action::MoveDown) {
-        xlat.z -= 1.0;
-    }
-    if action_state.pressed(Action::MoveRight) {
-        xlat.x -= 1.0;
-    }
-    let joystick_move = action_state.axis_pair(Action::Move).unwrap_or_default();
-    xlat.x -= joystick_move.x();
-    xlat.z += joystick_move.y();
-    let vel = xlat.normalize_or_zero() * speed * t.delta_seconds();
-    player_velocity.0.x = vel.x;
-    player_velocity.0.z = vel.z;
-    let mut aim = xform.rotation;
-    if action_state.pressed(Action::AimUp) {
-        aim = Quat::from_rotation_y(0.5 * PI);
-    } else if action_state.pressed(Action::AimDown) {
-        aim = Quat::from_rotation_y(1.5 * PI);
-    } else if action_state.pressed(Action::AimRight) {
-        aim = Quat::from_rotation_y(0.0);
-    } else if action_state.pressed(Action::AimLeft) {
-        aim = Quat::from_rotation_y(PI);
-    } else if action_state.axis_pair(Action::Aim).is_some() {
-        let aim_joystick = action_state.axis_pair(Action::Aim).unwrap();
-        let aim_x = aim_joystick.x();
-        let aim_y = aim_joystick.y();
-        let magnitude = (aim_x * aim_x + aim_y * aim_y).sqrt();
-        if magnitude > 0.2 && aim_x.abs() > 0.0 {
-            aim = Quat::from_rotation_y(aim_y.atan2(aim_x));
+    // The mouse belongs to the first player, so aim through whichever
+    // camera is showing it.
+    let cursor_ray = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .zip(camera_query.iter().find(|(camera, _, follows)| {
+            camera.is_active && follows.is_none_or(|follows| follows.index == 0)
+        }))
+        .and_then(|(cursor, (camera, camera_xform, _))| {
+            let corner = camera
+                .logical_viewport_rect()
+                .map_or(Vec2::ZERO, |rect| rect.min);
+            camera.viewport_to_world(camera_xform, cursor - corner)
+        });
+    for (controller, action_state, mut intent, xform, devices) in query.iter_mut() {
+        if *controller != Controller::Input {
+            continue;
+        }
+        // Each action has a button-like state of its own that you can check
+        let mut xlat = Vec3::new(0.0, 0.0, 0.0);
+        if action_state.pressed(Action::MoveUp) {
+            xlat.z += 1.0;
+        }
+        if action_state.pressed(Action::MoveLeft) {
+            xlat.x += 1.0;
         }
+        if action_state.pressed(Action::MoveDown) {
+            xlat.z -= 1.0;
+        }
+        if action_state.pressed(Action::MoveRight) {
+            xlat.x -= 1.0;
+        }
+        let joystick_move = action_state.axis_pair(Action::Move).unwrap_or_default();
+        xlat.x -= joystick_move.x();
+        xlat.z += joystick_move.y();
+        intent.movement = xlat.normalize_or_zero();
+
+        let stick_aim = action_state
+            .axis_pair(Action::Aim)
+            .map(|stick| Vec3::new(-stick.x(), 0.0, stick.y()))
+            .filter(|aim| aim.length() > 0.2 && aim.x.abs() > 0.0);
+        intent.aim = match *aim_mode {
+            AimMode::Keys if action_state.pressed(Action::AimUp) => Some(Vec3::Z),
+            AimMode::Keys if action_state.pressed(Action::AimDown) => Some(Vec3::NEG_Z),
+            AimMode::Keys if action_state.pressed(Action::AimRight) => Some(Vec3::NEG_X),
+            AimMode::Keys if action_state.pressed(Action::AimLeft) => Some(Vec3::X),
+            AimMode::Keys => stick_aim,
+            // Only the player with the mouse aims with it.
+            AimMode::Mouse => stick_aim.or_else(|| {
+                cursor_ray
+                    .filter(|_| devices.keyboard)
+                    .and_then(|ray| cursor_aim(ray, xform.translation))
+            }),
+        };
+        // Input is read every frame but acted on every fixed tick, so a
+        // press waits for `fire_gun` rather than being lost or repeated.
+        intent.fire |= action_state.just_pressed(Action::Shoot);
     }
-    player.aim = aim.to_euler(EulerRot::YZX).0;
-    xform.rotation = xform.rotation.slerp(aim, 0.1);
 }
 
-pub fn move_camera(
-    mut camera_query: Query<(&mut Transform, &Camera3d), Without<player::Player>>,
-    player_query: Query<(&Transform, &player::Player)>,
-    time: Res<Time>,
-) {
-    let (player_transform, player) = player_query.single();
-    let (mut camera_transform, camera) = camera_query.single_mut();
-    let delta = time.delta_seconds();
-    let threshold = 3.0;
-    let camera_speed = 6.0;
-    let mut camera_projection = camera_transform.translation.clone();
-    camera_projection.y = 0.0;
-    let mut player_projection = player_transform.translation.clone();
-    player_projection.y = 0.0;
+/// Direction from `from` to where `ray` meets the ground, measured at
+/// `from`'s height so the aim stays level.
+fn cursor_aim(ray: Ray, from: Vec3) -> Option<Vec3> {
+    let distance = ray.intersect_plane(Vec3::new(0.0, from.y, 0.0), Vec3::Y)?;
+    let mut aim = ray.get_point(distance) - from;
+    aim.y = 0.0;
+    // Right on top of the player the direction is meaningless.
+    (aim.length() > 0.1).then_some(aim.normalize())
+}
 
-    let distance = player_projection - camera_projection;
-    if distance.length() > threshold {
-        camera_transform.translation += distance / distance.length() * camera_speed * delta;
+pub fn move_player(
+    mut query: Query<(
+        &Intent,
+        &mut LinearVelocity,
+        &mut player::Player,
+        &mut Transform,
+    )>,
+) {
+    // Units per second at full tilt.  A velocity, so it doesn't depend on
+    // the frame or tick rate.
+    let speed = 5.0;
+    for (intent, mut player_velocity, mut player, mut xform) in query.iter_mut() {
+        let vel = intent.movement.clamp_length_max(1.0) * speed;
+        player_velocity.0.x = vel.x;
+        player_velocity.0.z = vel.z;
+        let aim = match intent.aim {
+            Some(direction) => Quat::from_rotation_y(direction.z.atan2(-direction.x)),
+            None => xform.rotation,
+        };
+        player.aim = aim.to_euler(EulerRot::YZX).0;
+        xform.rotation = xform.rotation.slerp(aim, 0.1);
     }
 }
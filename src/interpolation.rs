@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+
+/// Smooths the movement of an entity moved on the fixed timestep.  Gameplay
+/// and physics only move it once a tick, so in between it is drawn part way
+/// from where the previous tick left it to where the latest one did.
+#[derive(Component)]
+pub struct Interpolated {
+    previous: Transform,
+    current: Transform,
+}
+
+impl Interpolated {
+    pub fn new(transform: Transform) -> Self {
+        Interpolated {
+            previous: transform,
+            current: transform,
+        }
+    }
+}
+
+/// Puts back the latest tick's transforms before the fixed timestep runs,
+/// so gameplay and physics never see the in-between ones that are drawn.
+pub fn restore_transforms(mut query: Query<(&Interpolated, &mut Transform)>) {
+    for (interpolated, mut xform) in query.iter_mut() {
+        if *xform != interpolated.current {
+            *xform = interpolated.current;
+        }
+    }
+}
+
+/// Remembers where each tick left things, to draw between.
+pub fn store_transforms(mut query: Query<(&mut Interpolated, &Transform)>) {
+    for (mut interpolated, xform) in query.iter_mut() {
+        interpolated.previous = interpolated.current;
+        interpolated.current = *xform;
+    }
+}
+
+/// Places entities between their last two ticks by how far the clock has
+/// got towards the next one.
+pub fn interpolate_transforms(
+    time: Res<Time<Fixed>>,
+    mut query: Query<(&Interpolated, &mut Transform)>,
+) {
+    let t = time.overstep_percentage();
+    for (interpolated, mut xform) in query.iter_mut() {
+        let Interpolated { previous, current } = interpolated;
+        xform.translation = previous.translation.lerp(current.translation, t);
+        xform.rotation = previous.rotation.slerp(current.rotation, t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy_xpbd_3d::prelude::*;
+
+    use super::*;
+    use crate::controller::{ControllerSet, Intent};
+    use crate::gameplay::{headless_app, spawn_test_player, GameplaySet};
+    use crate::player::Player;
+
+    /// Where the player was at the end of each tick.
+    #[derive(Resource, Default)]
+    struct Ticks(Vec<Transform>);
+
+    /// Walks the player right, then up and to the right, turning as it
+    /// goes.  After 96 ticks it stands still.
+    fn follow_script(mut tick: Local<usize>, mut query: Query<&mut Intent, With<Player>>) {
+        let intent = Intent {
+            movement: match *tick {
+                0..=39 => Vec3::X,
+                40..=95 => Vec3::new(0.3, 0.0, -1.0),
+                _ => Vec3::ZERO,
+            },
+            aim: tick
+                .is_multiple_of(3)
+                .then_some(Vec3::new(-1.0, 0.0, *tick as f32 / 50.0)),
+            fire: false,
+        };
+        *tick += 1;
+        for mut current in query.iter_mut() {
+            *current = intent;
+        }
+    }
+
+    fn log_ticks(mut ticks: ResMut<Ticks>, query: Query<&Transform, With<Player>>) {
+        ticks.0.extend(query.iter());
+    }
+
+    /// Walks a player through the script at `fps` frames a second for
+    /// `seconds`, through the game's own fixed timestep and drawing, and
+    /// returns where each tick left it.
+    fn run(fps: f64, seconds: f64) -> Vec<Transform> {
+        let mut app = headless_app(Duration::from_secs_f64(1.0 / fps));
+        app.init_resource::<Ticks>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    follow_script.in_set(ControllerSet).in_set(GameplaySet),
+                    log_ticks.after(store_transforms),
+                ),
+            )
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Static,
+                Collider::cuboid(100.0, 0.01, 100.0),
+            ));
+        spawn_test_player(&mut app, 1.0, 2.0);
+        for _ in 0..(seconds * fps).round() as usize {
+            app.update();
+        }
+        app.world.remove_resource::<Ticks>().unwrap().0
+    }
+
+    #[test]
+    fn movement_does_not_depend_on_the_frame_rate() {
+        let slow = run(30.0, 3.0);
+        let fast = run(144.0, 3.0);
+        assert!(slow.len() >= 180 && fast.len() >= 180);
+        assert!(slow[179].translation.distance(slow[0].translation) > 1.0);
+        for (tick, (slow, fast)) in slow.iter().zip(&fast).enumerate() {
+            assert!(
+                slow.translation.distance(fast.translation) < 1e-4,
+                "tick {tick}: {slow:?} != {fast:?}"
+            );
+            assert!(
+                slow.rotation.angle_between(fast.rotation) < 1e-4,
+                "tick {tick}: {slow:?} != {fast:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::config::EnemyWeight;
+use super::generators::MazeAlgorithm;
+use super::waves::WaveConfig;
+use super::{GameMode, LevelConfig, Objective};
+
+/// One level of a campaign.  Everything not listed here, like wall sizes,
+/// comes from the [`LevelConfig`].
+#[derive(Deserialize, Clone, Debug)]
+pub struct CampaignLevel {
+    pub maze_width: usize,
+    pub maze_height: usize,
+    /// Overrides the config's maze algorithm for this level.
+    #[serde(default)]
+    pub algorithm: Option<MazeAlgorithm>,
+    /// Overrides the config's braid factor for this level.
+    #[serde(default)]
+    pub braid: Option<f32>,
+    /// How many walls are knocked out to make loops; more means fewer dead ends.
+    pub removed_walls: usize,
+    pub enemies: usize,
+    /// Overrides the config's enemy table for this level.
+    #[serde(default)]
+    pub enemy_table: Option<Vec<EnemyWeight>>,
+    /// Overrides the config's waves for this level.
+    #[serde(default)]
+    pub waves: Option<WaveConfig>,
+    pub ammo_regen_time: f32,
+    /// Overrides the config's objective for this level.
+    #[serde(default)]
+    pub objective: Option<Objective>,
+}
+
+/// The sequence of levels played one after another, read from
+/// `assets/main.campaign.ron`.
+#[derive(Asset, TypePath, Deserialize, Clone, Debug)]
+pub struct Campaign {
+    pub levels: Vec<CampaignLevel>,
+}
+
+#[derive(Resource)]
+pub struct CampaignHandle(pub Handle<Campaign>);
+
+/// Which campaign level is being played, and how many there are.
+#[derive(Resource, Default)]
+pub struct CampaignProgress {
+    pub level: usize,
+    pub levels: usize,
+}
+
+impl CampaignProgress {
+    pub fn is_last_level(&self) -> bool {
+        self.level + 1 >= self.levels
+    }
+}
+
+/// The settings for the level about to be built: the campaign's current
+/// level on top of the [`LevelConfig`], or the config alone if the
+/// campaign hasn't loaded.  Survival mode always uses the config.
+#[derive(SystemParam)]
+pub struct CurrentLevel<'w> {
+    mode: Res<'w, GameMode>,
+    config: Res<'w, LevelConfig>,
+    campaign: Res<'w, CampaignHandle>,
+    campaigns: Res<'w, Assets<Campaign>>,
+    progress: ResMut<'w, CampaignProgress>,
+}
+
+impl<'w> CurrentLevel<'w> {
+    pub fn config(&mut self) -> LevelConfig {
+        if *self.mode == GameMode::Survival {
+            self.progress.levels = 1;
+            return self.config.survival();
+        }
+        match self.campaigns.get(&self.campaign.0) {
+            Some(campaign) if !campaign.levels.is_empty() => {
+                self.progress.levels = campaign.levels.len();
+                let level = self.progress.level.min(campaign.levels.len() - 1);
+                self.config.with_level(&campaign.levels[level])
+            }
+            _ => {
+                self.progress.levels = 1;
+                self.config.clone()
+            }
+        }
+    }
+}
+
+pub fn load_campaign(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(CampaignHandle(assets.load("main.campaign.ron")));
+}
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::campaign::CampaignLevel;
+use super::generators::MazeAlgorithm;
+use super::waves::WaveConfig;
+use super::Objective;
+
+/// Tunable level parameters, read from `assets/default.level.ron`.
+///
+/// The file is watched, so edits are picked up while the game is running.
+/// Until it has loaded, the values from [`Default`] are used.
+#[derive(Asset, Resource, TypePath, Deserialize, Clone, Debug)]
+pub struct LevelConfig {
+    pub maze_width: usize,
+    pub maze_height: usize,
+    pub wall_length: f32,
+    pub wall_height: f32,
+    pub wall_thickness: f32,
+    #[serde(default)]
+    pub algorithm: MazeAlgorithm,
+    /// Fraction of dead ends (0.0 to 1.0) opened up into loops.
+    #[serde(default)]
+    pub braid: f32,
+    /// How many random walls are knocked out after braiding.
+    pub removed_walls: usize,
+    pub enemies: usize,
+    /// Which kinds of enemy to spawn and how often.  Left empty, every
+    /// enemy is the default bug.
+    #[serde(default)]
+    pub enemy_table: Vec<EnemyWeight>,
+    /// Bring enemies in over time instead of all at the start.  When set,
+    /// `enemies` is ignored in favour of the waves' sizes.
+    #[serde(default)]
+    pub waves: Option<WaveConfig>,
+    /// Seconds it takes the player to get one bullet back.
+    pub ammo_regen_time: f32,
+    #[serde(default)]
+    pub objective: Objective,
+    /// Play every level with this seed instead of a random one.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// An entry in a level's enemy table.  Each enemy is a kind picked with
+/// probability proportional to its weight.
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnemyWeight {
+    /// The [`EnemyKind`](crate::enemy::EnemyKind)'s name.
+    pub kind: String,
+    pub weight: u32,
+}
+
+impl Default for LevelConfig {
+    fn default() -> Self {
+        LevelConfig {
+            maze_width: 30,
+            maze_height: 20,
+            wall_length: 4.0,
+            wall_height: 6.0,
+            wall_thickness: 1.0,
+            algorithm: MazeAlgorithm::RecursiveBacktracker,
+            braid: 0.0,
+            removed_walls: 10,
+            enemies: 40,
+            enemy_table: Vec::new(),
+            waves: None,
+            ammo_regen_time: 1.0,
+            objective: Objective::KillAll,
+            seed: None,
+        }
+    }
+}
+
+impl LevelConfig {
+    /// This config with the per-level settings of a campaign level applied.
+    pub fn with_level(&self, level: &CampaignLevel) -> LevelConfig {
+        LevelConfig {
+            maze_width: level.maze_width,
+            maze_height: level.maze_height,
+            algorithm: level.algorithm.unwrap_or(self.algorithm),
+            braid: level.braid.unwrap_or(self.braid),
+            removed_walls: level.removed_walls,
+            enemies: level.enemies,
+            enemy_table: level
+                .enemy_table
+                .clone()
+                .unwrap_or_else(|| self.enemy_table.clone()),
+            waves: level.waves.clone().or_else(|| self.waves.clone()),
+            ammo_regen_time: level.ammo_regen_time,
+            objective: level.objective.unwrap_or(self.objective),
+            ..self.clone()
+        }
+    }
+
+    /// This config set up for survival mode: endless waves, using the
+    /// config's own waves if it has any.
+    pub fn survival(&self) -> LevelConfig {
+        let mut waves = self.waves.clone().unwrap_or_default();
+        waves.endless = true;
+        LevelConfig {
+            objective: Objective::Survival,
+            waves: Some(waves),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct LevelConfigHandle(pub Handle<LevelConfig>);
+
+pub fn load_level_config(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(LevelConfigHandle(assets.load("default.level.ron")));
+}
+
+/// Copies the config asset into the [`LevelConfig`] resource whenever it
+/// finishes loading or changes on disk.
+pub fn apply_level_config(
+    mut events: EventReader<AssetEvent<LevelConfig>>,
+    handle: Res<LevelConfigHandle>,
+    configs: Res<Assets<LevelConfig>>,
+    mut config: ResMut<LevelConfig>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }
+                if *id == handle.0.id() =>
+            {
+                if let Some(loaded) = configs.get(*id) {
+                    info!("Level config loaded: {:?}", loaded);
+                    *config = loaded.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+
+use super::layout::{Cell, MazeLayout};
+
+/// Something that can carve a maze out of a closed grid.  All the
+/// generators here produce perfect mazes (or, for rooms, a single
+/// connected layout); loops are added afterwards by braiding.
+pub trait MazeGenerator {
+    fn generate(&self, width: usize, height: usize, rng: &mut dyn RngCore) -> MazeLayout;
+}
+
+/// The generator to use for a level, as named in the level config.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MazeAlgorithm {
+    /// Long winding corridors with few, long dead ends.
+    #[default]
+    RecursiveBacktracker,
+    /// Lots of short dead ends branching off everywhere.
+    Prim,
+    /// Evenly spread, fairly short dead ends.
+    Kruskal,
+    /// Built row by row; tends towards horizontal corridors.
+    Eller,
+    /// Rectangular open rooms joined by maze corridors.
+    RoomsAndCorridors { rooms: usize, max_room_size: usize },
+}
+
+impl MazeAlgorithm {
+    pub fn generator(&self) -> Box<dyn MazeGenerator> {
+        match *self {
+            MazeAlgorithm::RecursiveBacktracker => Box::new(RecursiveBacktracker),
+            MazeAlgorithm::Prim => Box::new(Prim),
+            MazeAlgorithm::Kruskal => Box::new(Kruskal),
+            MazeAlgorithm::Eller => Box::new(Eller),
+            MazeAlgorithm::RoomsAndCorridors {
+                rooms,
+                max_room_size,
+            } => Box::new(RoomsAndCorridors {
+                rooms,
+                max_room_size,
+            }),
+        }
+    }
+}
+
+pub struct RecursiveBacktracker;
+pub struct Prim;
+pub struct Kruskal;
+pub struct Eller;
+pub struct RoomsAndCorridors {
+    pub rooms: usize,
+    pub max_room_size: usize,
+}
+
+impl MazeGenerator for RecursiveBacktracker {
+    fn generate(&self, width: usize, height: usize, rng: &mut dyn RngCore) -> MazeLayout {
+        let mut layout = MazeLayout::closed(width, height);
+        let mut visited = vec![false; width * height];
+        carve_from(&mut layout, (0, 0), &mut visited, rng);
+        layout
+    }
+}
+
+/// Depth-first carving from `start` through every cell not yet marked in
+/// `visited`.
+fn carve_from(layout: &mut MazeLayout, start: Cell, visited: &mut [bool], rng: &mut dyn RngCore) {
+    let mut stack = vec![start];
+    visited[layout.index(start)] = true;
+    while let Some(&cell) = stack.last() {
+        let unvisited: Vec<Cell> = layout
+            .neighbours(cell)
+            .into_iter()
+            .filter(|&n| !visited[layout.index(n)])
+            .collect();
+        match unvisited.choose(rng) {
+            Some(&next) => {
+                layout.remove_wall(cell, next);
+                visited[layout.index(next)] = true;
+                stack.push(next);
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+}
+
+impl MazeGenerator for Prim {
+    fn generate(&self, width: usize, height: usize, rng: &mut dyn RngCore) -> MazeLayout {
+        let mut layout = MazeLayout::closed(width, height);
+        let mut in_maze = vec![false; width * height];
+        let start = (rng.gen_range(0..width), rng.gen_range(0..height));
+        in_maze[layout.index(start)] = true;
+        let mut frontier: Vec<(Cell, Cell)> = layout
+            .neighbours(start)
+            .into_iter()
+            .map(|n| (start, n))
+            .collect();
+        while !frontier.is_empty() {
+            let (from, to) = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+            if in_maze[layout.index(to)] {
+                continue;
+            }
+            layout.remove_wall(from, to);
+            in_maze[layout.index(to)] = true;
+            for next in layout.neighbours(to) {
+                if !in_maze[layout.index(next)] {
+                    frontier.push((to, next));
+                }
+            }
+        }
+        layout
+    }
+}
+
+impl MazeGenerator for Kruskal {
+    fn generate(&self, width: usize, height: usize, rng: &mut dyn RngCore) -> MazeLayout {
+        let mut layout = MazeLayout::closed(width, height);
+        let mut walls = layout.walls();
+        walls.shuffle(rng);
+        let mut sets = DisjointSets::new(width * height);
+        for (a, b) in walls {
+            if sets.union(layout.index(a), layout.index(b)) {
+                layout.remove_wall(a, b);
+            }
+        }
+        layout
+    }
+}
+
+impl MazeGenerator for Eller {
+    fn generate(&self, width: usize, height: usize, rng: &mut dyn RngCore) -> MazeLayout {
+        let mut layout = MazeLayout::closed(width, height);
+        // Set ids for the current row; 0 means "not in a set yet".
+        let mut row = vec![0; width];
+        let mut next_set = 1;
+        for y in 0..height {
+            for set in row.iter_mut().filter(|set| **set == 0) {
+                *set = next_set;
+                next_set += 1;
+            }
+            // Randomly join neighbours in different sets.  The last row
+            // must join everything that's still apart.
+            let last_row = y + 1 == height;
+            for x in 0..width.saturating_sub(1) {
+                if row[x] != row[x + 1] && (last_row || rng.gen_bool(0.5)) {
+                    layout.remove_wall((x, y), (x + 1, y));
+                    let (keep, merged) = (row[x], row[x + 1]);
+                    for set in row.iter_mut().filter(|set| **set == merged) {
+                        *set = keep;
+                    }
+                }
+            }
+            if last_row {
+                break;
+            }
+            // Every set carries on into the next row at least once.
+            let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for (x, &set) in row.iter().enumerate() {
+                members.entry(set).or_default().push(x);
+            }
+            let mut next_row = vec![0; width];
+            for (set, mut xs) in members {
+                xs.shuffle(rng);
+                for (i, x) in xs.into_iter().enumerate() {
+                    if i == 0 || rng.gen_bool(0.5) {
+                        layout.remove_wall((x, y), (x, y + 1));
+                        next_row[x] = set;
+                    }
+                }
+            }
+            row = next_row;
+        }
+        layout
+    }
+}
+
+impl MazeGenerator for RoomsAndCorridors {
+    fn generate(&self, width: usize, height: usize, rng: &mut dyn RngCore) -> MazeLayout {
+        let mut layout = MazeLayout::closed(width, height);
+        let mut region = vec![None; width * height];
+        let mut regions = 0;
+
+        // Open up rooms where they don't overlap an earlier one.
+        let max_size = self.max_room_size.max(2);
+        for _ in 0..self.rooms * 4 {
+            if regions == self.rooms {
+                break;
+            }
+            let room_width = rng.gen_range(2..=max_size).min(width);
+            let room_height = rng.gen_range(2..=max_size).min(height);
+            let x0 = rng.gen_range(0..=width - room_width);
+            let y0 = rng.gen_range(0..=height - room_height);
+            let cells: Vec<Cell> = (y0..y0 + room_height)
+                .flat_map(|y| (x0..x0 + room_width).map(move |x| (x, y)))
+                .collect();
+            if cells.iter().any(|&c| region[layout.index(c)].is_some()) {
+                continue;
+            }
+            for &cell in &cells {
+                region[layout.index(cell)] = Some(regions);
+                for next in layout.neighbours(cell) {
+                    if cells.contains(&next) {
+                        layout.remove_wall(cell, next);
+                    }
+                }
+            }
+            regions += 1;
+        }
+
+        // Fill the space between the rooms with corridors.
+        let mut visited: Vec<bool> = region.iter().map(|r| r.is_some()).collect();
+        for y in 0..height {
+            for x in 0..width {
+                if !visited[layout.index((x, y))] {
+                    let before = visited.clone();
+                    carve_from(&mut layout, (x, y), &mut visited, rng);
+                    for i in 0..visited.len() {
+                        if visited[i] && !before[i] {
+                            region[i] = Some(regions);
+                        }
+                    }
+                    regions += 1;
+                }
+            }
+        }
+
+        // Knock through just enough walls between regions to join them all.
+        let mut connectors: Vec<(Cell, Cell)> = layout
+            .walls()
+            .into_iter()
+            .filter(|&(a, b)| region[layout.index(a)] != region[layout.index(b)])
+            .collect();
+        connectors.shuffle(rng);
+        let mut sets = DisjointSets::new(regions);
+        for (a, b) in connectors {
+            let (ra, rb) = (region[layout.index(a)], region[layout.index(b)]);
+            if sets.union(ra.unwrap(), rb.unwrap()) {
+                layout.remove_wall(a, b);
+            }
+        }
+        layout
+    }
+}
+
+/// Union-find over `0..n`, used to track which cells or regions are
+/// already connected.
+struct DisjointSets {
+    parent: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(n: usize) -> Self {
+        DisjointSets {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    /// Joins the sets holding `a` and `b`.  Returns false if they were
+    /// already the same set.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            false
+        } else {
+            self.parent[b] = a;
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const WIDTH: usize = 12;
+    const HEIGHT: usize = 9;
+    const SEEDS: [u64; 5] = [0, 1, 7, 42, 1234];
+
+    fn algorithms() -> Vec<MazeAlgorithm> {
+        vec![
+            MazeAlgorithm::RecursiveBacktracker,
+            MazeAlgorithm::Prim,
+            MazeAlgorithm::Kruskal,
+            MazeAlgorithm::Eller,
+            MazeAlgorithm::RoomsAndCorridors {
+                rooms: 4,
+                max_room_size: 4,
+            },
+        ]
+    }
+
+    fn generate(algorithm: MazeAlgorithm, seed: u64) -> MazeLayout {
+        let mut rng = StdRng::seed_from_u64(seed);
+        algorithm.generator().generate(WIDTH, HEIGHT, &mut rng)
+    }
+
+    /// Number of open passages between neighbouring cells.
+    fn passages(layout: &MazeLayout) -> usize {
+        let all = (layout.width - 1) * layout.height + layout.width * (layout.height - 1);
+        all - layout.walls().len()
+    }
+
+    #[test]
+    fn every_generator_connects_every_cell() {
+        for algorithm in algorithms() {
+            for seed in SEEDS {
+                let layout = generate(algorithm, seed);
+                let distances = layout.distances_from((0, 0));
+                assert!(
+                    distances.iter().all(Option::is_some),
+                    "{algorithm:?} with seed {seed} left cells unreachable"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mazes_without_rooms_are_perfect() {
+        for algorithm in algorithms() {
+            if let MazeAlgorithm::RoomsAndCorridors { .. } = algorithm {
+                continue;
+            }
+            for seed in SEEDS {
+                let layout = generate(algorithm, seed);
+                assert_eq!(
+                    passages(&layout),
+                    WIDTH * HEIGHT - 1,
+                    "{algorithm:?} with seed {seed} has loops"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_maze() {
+        for algorithm in algorithms() {
+            for seed in SEEDS {
+                assert_eq!(
+                    generate(algorithm, seed).walls(),
+                    generate(algorithm, seed).walls(),
+                    "{algorithm:?} with seed {seed} isn't repeatable"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn disjoint_sets_join_only_once() {
+        let mut sets = DisjointSets::new(5);
+        assert!(sets.union(0, 1));
+        assert!(sets.union(2, 3));
+        assert!(!sets.union(1, 0));
+        assert!(sets.union(1, 3));
+        assert!(!sets.union(0, 2));
+        assert_eq!(sets.find(0), sets.find(3));
+        assert_ne!(sets.find(0), sets.find(4));
+    }
+}
@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+
+use super::layout::Cell;
+use super::LevelConfig;
+
+/// Where the cells of the current maze are in the world.  The maze is
+/// centred on the origin, with cell `(0, 0)` at the -X/-Z corner.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct MazeGrid {
+    pub width: usize,
+    pub height: usize,
+    pub wall_length: f32,
+    pub wall_thickness: f32,
+}
+
+impl MazeGrid {
+    pub fn new(width: usize, height: usize, config: &LevelConfig) -> Self {
+        MazeGrid {
+            width,
+            height,
+            wall_length: config.wall_length,
+            wall_thickness: config.wall_thickness,
+        }
+    }
+
+    /// Distance between the centres of neighbouring cells.
+    pub fn cell_size(&self) -> f32 {
+        self.wall_length + self.wall_thickness
+    }
+
+    /// The world position of the -X/-Z corner of the maze, outside walls included.
+    pub fn origin(&self) -> Vec2 {
+        Vec2::new(
+            (self.width as f32 * self.cell_size() + self.wall_thickness) / -2.0,
+            (self.height as f32 * self.cell_size() + self.wall_thickness) / -2.0,
+        )
+    }
+
+    /// The centre of `cell` on the ground, at height `y`.
+    pub fn cell_center(&self, (x, z): Cell, y: f32) -> Vec3 {
+        let origin = self.origin();
+        let offset = self.wall_thickness + self.wall_length / 2.0;
+        Vec3::new(
+            origin.x + offset + x as f32 * self.cell_size(),
+            y,
+            origin.y + offset + z as f32 * self.cell_size(),
+        )
+    }
+
+    /// Where the grid lines on the -X/-Z sides of `cell` cross, at height
+    /// `y`.  This is where wall posts go; `(width, height)` is the far
+    /// corner of the maze.
+    pub fn corner(&self, (x, z): Cell, y: f32) -> Vec3 {
+        let origin = self.origin();
+        Vec3::new(
+            origin.x + self.wall_thickness / 2.0 + x as f32 * self.cell_size(),
+            y,
+            origin.y + self.wall_thickness / 2.0 + z as f32 * self.cell_size(),
+        )
+    }
+
+    /// The cell containing a world position, or `None` outside the maze.
+    pub fn cell_at(&self, position: Vec3) -> Option<Cell> {
+        let origin = self.origin();
+        let x = ((position.x - origin.x - self.wall_thickness / 2.0) / self.cell_size()).floor();
+        let z = ((position.z - origin.y - self.wall_thickness / 2.0) / self.cell_size()).floor();
+        if x < 0.0 || z < 0.0 || x as usize >= self.width || z as usize >= self.height {
+            None
+        } else {
+            Some((x as usize, z as usize))
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use bevy::prelude::Resource;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+pub type Cell = (usize, usize);
+
+/// The walls standing between neighbouring cells of a rectangular maze.
+/// The outer border isn't stored; it is always drawn.
+///
+/// Walls are kept in a `BTreeSet` so iterating them, and therefore every
+/// random choice made over them, is the same for a given seed.
+#[derive(Resource, Clone)]
+pub struct MazeLayout {
+    pub width: usize,
+    pub height: usize,
+    walls: BTreeSet<(Cell, Cell)>,
+}
+
+impl MazeLayout {
+    /// A grid with a wall between every pair of neighbouring cells.
+    pub fn closed(width: usize, height: usize) -> Self {
+        let mut walls = BTreeSet::new();
+        for j in 0..height {
+            for i in 0..width {
+                if i + 1 < width {
+                    walls.insert(((i, j), (i + 1, j)));
+                }
+                if j + 1 < height {
+                    walls.insert(((i, j), (i, j + 1)));
+                }
+            }
+        }
+        MazeLayout {
+            width,
+            height,
+            walls,
+        }
+    }
+
+    /// Every wall still standing, in a fixed order.
+    pub fn walls(&self) -> Vec<(Cell, Cell)> {
+        self.walls.iter().cloned().collect()
+    }
+
+    /// Knocks down `count` random walls, adding loops to the maze.
+    pub fn remove_random_walls(&mut self, count: usize, rng: &mut impl Rng) {
+        for _ in 0..count {
+            match self.walls().choose(rng) {
+                Some(&(a, b)) => {
+                    self.remove_wall(a, b);
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Opens up a `factor` fraction (0.0 to 1.0) of the dead ends.  At 0.0
+    /// the maze is left alone; at 1.0 there are no dead ends left.  A dead
+    /// end is joined to a neighbouring dead end where possible, so each
+    /// removed wall takes out as many dead ends as it can.
+    pub fn braid(&mut self, factor: f32, rng: &mut impl Rng) {
+        let mut dead_ends = self.dead_ends();
+        dead_ends.shuffle(rng);
+        let to_open = (dead_ends.len() as f32 * factor.clamp(0.0, 1.0)).round() as usize;
+        for cell in dead_ends.into_iter().take(to_open) {
+            // An earlier removal may already have opened this one up.
+            if self.open_neighbours(cell).len() != 1 {
+                continue;
+            }
+            let closed: Vec<Cell> = self
+                .neighbours(cell)
+                .into_iter()
+                .filter(|&n| self.has_edge(cell, n))
+                .collect();
+            let other_dead_ends: Vec<Cell> = closed
+                .iter()
+                .cloned()
+                .filter(|&n| self.open_neighbours(n).len() == 1)
+                .collect();
+            let choice = if other_dead_ends.is_empty() {
+                closed.choose(rng)
+            } else {
+                other_dead_ends.choose(rng)
+            };
+            if let Some(&next) = choice {
+                self.remove_wall(cell, next);
+            }
+        }
+    }
+
+    /// Cells with only one way out.
+    pub fn dead_ends(&self) -> Vec<Cell> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&cell| self.open_neighbours(cell).len() == 1)
+            .collect()
+    }
+
+    pub fn has_edge(&self, a: Cell, b: Cell) -> bool {
+        self.walls.contains(&ordered(a, b))
+    }
+
+    pub fn remove_wall(&mut self, a: Cell, b: Cell) -> bool {
+        self.walls.remove(&ordered(a, b))
+    }
+
+    /// The in-bounds cells sharing a side with `cell`, walls or not.
+    pub fn neighbours(&self, (x, y): Cell) -> Vec<Cell> {
+        let mut cells = Vec::with_capacity(4);
+        if x > 0 {
+            cells.push((x - 1, y));
+        }
+        if x + 1 < self.width {
+            cells.push((x + 1, y));
+        }
+        if y > 0 {
+            cells.push((x, y - 1));
+        }
+        if y + 1 < self.height {
+            cells.push((x, y + 1));
+        }
+        cells
+    }
+
+    /// The neighbouring cells reachable from `cell` without crossing a wall.
+    pub fn open_neighbours(&self, cell: Cell) -> Vec<Cell> {
+        self.neighbours(cell)
+            .into_iter()
+            .filter(|&n| !self.has_edge(cell, n))
+            .collect()
+    }
+
+    pub fn index(&self, (x, y): Cell) -> usize {
+        y * self.width + x
+    }
+
+    /// Number of steps from `start` to every cell, indexed by
+    /// [`MazeLayout::index`].  Unreachable cells are `None`.
+    pub fn distances_from(&self, start: Cell) -> Vec<Option<usize>> {
+        self.distances_from_any(&[start])
+    }
+
+    /// Steps from each cell to the closest of `starts`, or `None` for cells
+    /// none of them can reach.
+    pub fn distances_from_any(&self, starts: &[Cell]) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.width * self.height];
+        let mut queue = VecDeque::from(starts.to_vec());
+        for &start in starts {
+            distances[self.index(start)] = Some(0);
+        }
+        while let Some(cell) = queue.pop_front() {
+            let next = distances[self.index(cell)].unwrap() + 1;
+            for neighbour in self.open_neighbours(cell) {
+                if distances[self.index(neighbour)].is_none() {
+                    distances[self.index(neighbour)] = Some(next);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        distances
+    }
+
+    /// The reachable cell with the longest path from `start`.
+    pub fn farthest_from(&self, start: Cell) -> Cell {
+        let distances = self.distances_from(start);
+        let mut farthest = (start, 0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if let Some(distance) = distances[self.index((x, y))] {
+                    if distance > farthest.1 {
+                        farthest = ((x, y), distance);
+                    }
+                }
+            }
+        }
+        farthest.0
+    }
+}
+
+fn ordered(a: Cell, b: Cell) -> (Cell, Cell) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// A corridor along row 0 with a dead-end column opening off every
+    /// cell of it.
+    fn comb(width: usize, height: usize) -> MazeLayout {
+        let mut layout = MazeLayout::closed(width, height);
+        for x in 0..width {
+            if x + 1 < width {
+                layout.remove_wall((x, 0), (x + 1, 0));
+            }
+            for y in 0..height - 1 {
+                layout.remove_wall((x, y), (x, y + 1));
+            }
+        }
+        layout
+    }
+
+    #[test]
+    fn distances_count_steps_from_the_closest_start() {
+        let layout = comb(3, 3);
+        let distances = layout.distances_from_any(&[(0, 2), (2, 2)]);
+        assert_eq!(distances[layout.index((0, 2))], Some(0));
+        assert_eq!(distances[layout.index((0, 0))], Some(2));
+        assert_eq!(distances[layout.index((1, 0))], Some(3));
+        assert_eq!(distances[layout.index((1, 2))], Some(5));
+        assert_eq!(layout.farthest_from((0, 2)), (2, 2));
+    }
+
+    #[test]
+    fn closed_cells_are_unreachable() {
+        let layout = MazeLayout::closed(2, 2);
+        let distances = layout.distances_from((0, 0));
+        assert_eq!(distances, vec![Some(0), None, None, None]);
+    }
+
+    #[test]
+    fn full_braiding_leaves_no_dead_ends() {
+        let mut layout = comb(6, 4);
+        assert_eq!(layout.dead_ends().len(), 6);
+        let mut rng = StdRng::seed_from_u64(3);
+        layout.braid(0.0, &mut rng);
+        assert_eq!(layout.dead_ends().len(), 6);
+        layout.braid(1.0, &mut rng);
+        assert!(layout.dead_ends().is_empty());
+    }
+}
@@ -1,63 +1,369 @@
 use std::f32::consts::PI;
 
-use bevy::gltf::Gltf;
 use bevy::prelude::*;
 use bevy::{ecs::system::Commands, prelude::ResMut};
 use bevy_xpbd_3d::prelude::*;
-use maze::Edge;
-use oxidized_navigation::debug_draw::{DrawNavMesh, DrawPath};
-use oxidized_navigation::NavMeshAffector;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use crate::enemy::{self, EnemyCounts};
+use crate::bindings::Bindings;
+use crate::enemy::{self, EnemyCounts, EnemyKind, EnemyKinds};
+use crate::input::Seats;
+use crate::player::PlayerDied;
+use crate::ron_loader::RonLoader;
+use crate::state::{despawn_with, GameState};
+use crate::stats::RunStats;
 
-const wall_length: f32 = 4.0;
-const wall_height: f32 = 6.0;
-const wall_thickness: f32 = 1.0;
+mod campaign;
+mod config;
+mod generators;
+mod grid;
+mod layout;
+mod nav_mesh;
+mod objective;
+mod waves;
+pub use campaign::CampaignProgress;
+use campaign::CurrentLevel;
+use config::EnemyWeight;
+pub use config::LevelConfig;
+pub use grid::MazeGrid;
+pub use layout::{Cell, MazeLayout};
+pub use nav_mesh::build_nav_mesh;
+pub use objective::Objective;
+pub use waves::WaveSpawner;
+
+/// Enemies placed at the start of a level are at least this many cells'
+/// walk from the player.
+const MIN_SPAWN_STEPS: usize = 4;
 
 #[derive(Default)]
 pub struct LevelPlugin;
-#[derive(Resource)]
-pub struct Walls(Handle<Gltf>);
 #[derive(Component)]
 pub struct Wall;
+/// Marks everything that belongs to the current run, so the whole level
+/// can be torn down when going back to the title screen.
+#[derive(Component)]
+pub struct LevelEntity;
+/// Sent once every enemy in the level has been killed.
+#[derive(Event)]
+pub struct LevelCleared;
+/// The seed driving maze generation and enemy placement.  The same seed
+/// always produces the same level.
+#[derive(Resource, Clone, Copy)]
+pub struct LevelSeed(pub u64);
+/// Whether the levels come from the campaign, or it's one endless level
+/// of waves.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Campaign,
+    Survival,
+}
+/// Whether the next level reuses the current seed or rolls a fresh one.
+#[derive(Resource, Default, PartialEq, Eq)]
+pub enum NextMaze {
+    #[default]
+    New,
+    Retry,
+}
+
+impl LevelSeed {
+    /// Reads `--seed <n>` from the command line.
+    pub fn from_args() -> Option<LevelSeed> {
+        let mut args = std::env::args();
+        while let Some(arg) = args.next() {
+            if arg == "--seed" {
+                return args
+                    .next()
+                    .and_then(|seed| seed.parse().ok())
+                    .map(LevelSeed);
+            }
+        }
+        None
+    }
+}
 
 impl Plugin for LevelPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(Startup, setup);
+        // A seed given on the command line is used for the first level
+        // instead of a random one.
+        match LevelSeed::from_args() {
+            Some(seed) => app.insert_resource(seed).insert_resource(NextMaze::Retry),
+            None => app
+                .insert_resource(LevelSeed(0))
+                .insert_resource(NextMaze::New),
+        };
+        app.add_event::<LevelCleared>()
+            .init_asset::<LevelConfig>()
+            .init_asset::<campaign::Campaign>()
+            .register_asset_loader(RonLoader::<LevelConfig>::new(&["level.ron"]))
+            .register_asset_loader(RonLoader::<campaign::Campaign>::new(&["campaign.ron"]))
+            .init_resource::<LevelConfig>()
+            .init_resource::<CampaignProgress>()
+            .init_resource::<GameMode>()
+            .init_resource::<Objective>()
+            .add_systems(
+                Startup,
+                (
+                    spawn_camera,
+                    config::load_level_config,
+                    campaign::load_campaign,
+                ),
+            )
+            .add_systems(
+                OnEnter(GameState::Playing),
+                setup.run_if(not(any_with_component::<LevelEntity>())),
+            )
+            .add_systems(OnEnter(GameState::Title), despawn_with::<LevelEntity>)
+            .add_systems(OnExit(GameState::Victory), despawn_with::<LevelEntity>)
+            .add_systems(OnExit(GameState::GameOver), despawn_with::<LevelEntity>)
+            // Runs on the fixed timestep with the rest of the gameplay.
+            // `PlayerDied` is read in the same tick it is sent, so it can't
+            // be dropped on frames that run several ticks or none.
+            .add_systems(
+                FixedUpdate,
+                (
+                    waves::run_waves,
+                    check_level_cleared,
+                    objective::unlock_exit,
+                    objective::reach_exit,
+                    finish_level,
+                    fail_level,
+                )
+                    .chain()
+                    .in_set(crate::gameplay::GameplaySet)
+                    .after(crate::player::hurt_player),
+            )
+            .add_systems(
+                Update,
+                (
+                    config::apply_level_config,
+                    rebuild_level.run_if(
+                        resource_changed::<LevelConfig>().and_then(in_state(GameState::Playing)),
+                    ),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Tears down the level and builds it again from the same seed, so edits to
+/// the level config show up without leaving the game.
+fn rebuild_level(world: &mut World) {
+    let level: Vec<Entity> = world
+        .query_filtered::<Entity, With<LevelEntity>>()
+        .iter(world)
+        .collect();
+    for entity in level {
+        if let Some(entity) = world.get_entity_mut(entity) {
+            entity.despawn_recursive();
+        }
     }
+    // A seed set in the config replaces the current one, otherwise keep
+    // the maze we were looking at.
+    *world.resource_mut::<NextMaze>() = match world.resource::<LevelConfig>().seed {
+        Some(_) => NextMaze::New,
+        None => NextMaze::Retry,
+    };
+    world.run_schedule(OnEnter(GameState::Playing));
+}
+
+/// Whether the level config, campaign and enemy kinds have finished
+/// loading, so a level built now is the one the files describe.
+pub fn level_assets_loaded(
+    assets: Res<AssetServer>,
+    config: Res<config::LevelConfigHandle>,
+    campaign: Res<campaign::CampaignHandle>,
+    enemies: Res<enemy::EnemyKindsFolder>,
+) -> bool {
+    assets.is_loaded_with_dependencies(&config.0)
+        && assets.is_loaded_with_dependencies(&campaign.0)
+        && assets.is_loaded_with_dependencies(&enemies.0)
+}
+
+/// Cells enemies may start in: reachable from the player's `start` cell,
+/// and at least `MIN_SPAWN_STEPS` steps away from it, or as far as the
+/// maze allows if it is too small for that.
+fn spawn_cells(maze: &MazeLayout, start: Cell) -> Vec<Cell> {
+    let distances = maze.distances_from(start);
+    let farthest = distances.iter().flatten().cloned().max().unwrap_or(0);
+    let min_steps = MIN_SPAWN_STEPS.min(farthest).max(1);
+    (0..maze.height)
+        .flat_map(|y| (0..maze.width).map(move |x| (x, y)))
+        .filter(|&cell| distances[maze.index(cell)].is_some_and(|steps| steps >= min_steps))
+        .collect()
+}
+
+/// Picks an enemy kind from a weighted table, falling back to the default
+/// kind if the table is empty or names one that isn't loaded.
+fn choose_enemy_kind(table: &[EnemyWeight], kinds: &EnemyKinds, rng: &mut impl Rng) -> EnemyKind {
+    match table.choose_weighted(rng, |entry| entry.weight) {
+        Ok(entry) => kinds.get(&entry.kind).cloned().unwrap_or_else(|| {
+            warn!("Unknown enemy kind {:?}, spawning the default", entry.kind);
+            EnemyKind::default()
+        }),
+        Err(_) => EnemyKind::default(),
+    }
+}
+
+fn check_level_cleared(
+    objective: Res<Objective>,
+    enemy_counts: Res<EnemyCounts>,
+    spawner: Option<Res<WaveSpawner>>,
+    mut cleared: EventWriter<LevelCleared>,
+) {
+    if *objective == Objective::KillAll
+        && spawner.is_none_or(|spawner| spawner.is_finished())
+        && enemy_counts.count > 0
+        && enemy_counts.killed >= enemy_counts.count
+    {
+        cleared.send(LevelCleared);
+    }
+}
+
+fn finish_level(
+    mut cleared: EventReader<LevelCleared>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if cleared.read().next().is_some() {
+        next_state.set(GameState::Victory);
+    }
+}
+
+fn fail_level(mut died: EventReader<PlayerDied>, mut next_state: ResMut<NextState<GameState>>) {
+    if died.read().next().is_some() {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+fn camera_start() -> Transform {
+    Transform::from_xyz(3.5, 25.0, 3.5).looking_at(Vec3::ZERO, Vec3::Z)
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn(Camera3dBundle {
+        transform: camera_start(),
+        ..default()
+    });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     assets: ResMut<AssetServer>,
-    time: Res<Time>,
-    mut draw_mesh: ResMut<DrawNavMesh>,
-    enemy_counts: ResMut<EnemyCounts>,
+    time: Res<Time<Fixed>>,
+    mut enemy_counts: ResMut<EnemyCounts>,
+    mut camera_query: Query<&mut Transform, With<Camera3d>>,
+    mut seed: ResMut<LevelSeed>,
+    next_maze: Res<NextMaze>,
+    mut stats: ResMut<RunStats>,
+    mut level: CurrentLevel,
+    kinds: EnemyKinds,
+    bindings: Res<Bindings>,
+    seats: Seats,
 ) {
-    //draw_mesh.0 = true;
-    let maze_width = 30;
-    let maze_height = 20;
+    let config = level.config();
+    let maze_width = config.maze_width;
+    let maze_height = config.maze_height;
     // camera
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(3.5, 25.0, 3.5).looking_at(Vec3::ZERO, Vec3::Z),
+    *camera_query.single_mut() = camera_start();
+    enemy_counts.killed = 0;
+    enemy_counts.count = 0;
+    *stats = RunStats {
+        started: time.elapsed_seconds(),
         ..default()
-    });
-    crate::player::spawn_player(&mut commands, &assets, 3.5, 3.5);
-    let mut rng = rand::thread_rng();
-    for enemy_num in 1..enemy::enemies_to_spawn {
-        let mut enemy_x: i32 = 0;
-        let mut enemy_y: i32 = 0;
-        while (enemy_x.abs() < 3 && enemy_y.abs() < 3) {
-            enemy_x = rng.gen_range(-1 * maze_width as i32 / 2..maze_width as i32 / 2);
-            enemy_y = rng.gen_range(-1 * maze_height as i32 / 2..maze_height as i32 / 2);
+    };
+    if *next_maze == NextMaze::New {
+        seed.0 = config.seed.unwrap_or_else(rand::random);
+    }
+    let mut rng = StdRng::seed_from_u64(seed.0);
+    let mut maze = config
+        .algorithm
+        .generator()
+        .generate(maze_width, maze_height, &mut rng);
+    maze.braid(config.braid, &mut rng);
+    maze.remove_random_walls(config.removed_walls, &mut rng);
+    let grid = MazeGrid::new(maze_width, maze_height, &config);
+    let start = grid.cell_at(Vec3::new(3.5, 0.0, 3.5)).unwrap_or((0, 0));
+    let start_pos = grid.cell_center(start, 0.0);
+    crate::player::spawn_level_ui(&mut commands);
+    let seats = seats.devices();
+    for (index, &devices) in seats.iter().enumerate() {
+        let player = crate::player::spawn_player(
+            &mut commands,
+            &assets,
+            start_pos.x,
+            start_pos.z,
+            config.ammo_regen_time,
+            &bindings,
+            index,
+            seats.len(),
+            devices,
+        );
+        crate::player::spawn_player_ui(&mut commands, player, index);
+    }
+    if let Objective::ReachExit { .. } = config.objective {
+        let exit = maze.farthest_from(start);
+        objective::spawn_exit(&mut commands, &mut meshes, &mut materials, &grid, exit);
+    }
+    commands.insert_resource(config.objective);
+    commands.insert_resource(grid);
+    // With waves configured, the `WaveSpawner` brings the enemies in over
+    // time instead of placing them all now.
+    let all_at_start = match config.waves.clone() {
+        Some(waves) => {
+            let mut spawn_points = maze.dead_ends();
+            if spawn_points.is_empty() {
+                spawn_points = (0..maze_height)
+                    .flat_map(|y| (0..maze_width).map(move |x| (x, y)))
+                    .collect();
+            }
+            commands.insert_resource(WaveSpawner::new(
+                waves,
+                config.enemy_table.clone(),
+                spawn_points,
+                time.elapsed_seconds(),
+                StdRng::seed_from_u64(rng.gen()),
+            ));
+            0
+        }
+        None => {
+            commands.remove_resource::<WaveSpawner>();
+            config.enemies
         }
-        let enemy_x = enemy_x as f32 * (wall_length + wall_thickness);
-        let enemy_y = enemy_y as f32 * (wall_length + wall_thickness);
-        crate::enemy::spawn_enemy(&mut commands, &assets, &time, enemy_x + 3.5, enemy_y + 3.5);
+    };
+    let spawn_cells = spawn_cells(&maze, start);
+    for _ in 0..all_at_start {
+        let Some(&cell) = spawn_cells.choose(&mut rng) else {
+            break;
+        };
+        // Spread enemies sharing a cell around it rather than stacking
+        // them on the centre.
+        let spread = (grid.wall_length / 2.0 - 1.0).max(0.0);
+        let position = grid.cell_center(cell, 0.0)
+            + Vec3::new(
+                rng.gen_range(-spread..=spread),
+                0.0,
+                rng.gen_range(-spread..=spread),
+            );
+        let kind = choose_enemy_kind(&config.enemy_table, &kinds, &mut rng);
+        crate::enemy::spawn_enemy(
+            &mut commands,
+            &assets,
+            time.elapsed_seconds(),
+            &kind,
+            position.x,
+            position.z,
+        );
+        enemy_counts.count += 1;
     }
+    // Seeded last so the maze and enemy placement stay what they were for
+    // each seed.
+    commands.insert_resource(enemy::AiRng(StdRng::seed_from_u64(rng.gen())));
     // plane
     commands
         .spawn(PbrBundle {
@@ -71,46 +377,48 @@ fn setup(
             ..default()
         })
         .insert(RigidBody::Static)
-        .insert(NavMeshAffector)
+        .insert(LevelEntity)
         .insert(Collider::cuboid(500.0, 0.01, 500.0));
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            shadows_enabled: true,
-            illuminance: 5000.0,
-            color: Color::rgb(0.75, 0.75, 0.75),
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                shadows_enabled: true,
+                illuminance: 5000.0,
+                color: Color::rgb(0.75, 0.75, 0.75),
+                ..default()
+            },
+            transform: Transform::IDENTITY.looking_to(
+                Vec3 {
+                    x: 1.0,
+                    y: -2.0,
+                    z: 1.0,
+                }
+                .normalize(),
+                Vec3::Y,
+            ),
             ..default()
         },
-        transform: Transform::IDENTITY.looking_to(
-            Vec3 {
-                x: 1.0,
-                y: -2.0,
-                z: 1.0,
-            }
-            .normalize(),
-            Vec3::Y,
-        ),
-        ..default()
-    });
-    spawn_walls(commands, assets, maze_width, maze_height);
+        LevelEntity,
+    ));
+    let walls = wall_pieces(&maze, grid, config.wall_height);
+    let (nav_mesh_settings, nav_mesh) = nav_mesh::build_nav_mesh(&grid, &walls);
+    commands.insert_resource(nav_mesh_settings);
+    commands.insert_resource(nav_mesh);
+    spawn_walls(&mut commands, &assets, walls);
+    commands.insert_resource(maze);
 }
 
-fn spawn_walls(
-    commands: Commands,
-    assets: ResMut<AssetServer>,
-    maze_width: usize,
-    maze_height: usize,
-) -> () {
-    let mut maze = maze::Maze::new(maze_width, maze_height);
-    maze.add_walls();
-    let mut edge_vec: Vec<Edge> = maze.edges.iter().cloned().collect::<Vec<Edge>>();
-    for _ in 1..10 {
-        let mut rng = rand::thread_rng();
-        let index = rng.gen_range(0..edge_vec.len() - 1);
-        edge_vec.remove(index);
-        maze.edges.remove(&edge_vec[index]);
-    }
+/// A wall or post of the maze: where it goes and the shape it blocks.
+pub struct WallPiece {
+    pub transform: Transform,
+    pub collider: Collider,
+    post: bool,
+}
 
-    let mut ms = WallSpawner::new(commands, maze.height, maze.width, assets);
+/// Lays out the walls and posts of `maze`.  The same pieces are spawned
+/// and built into the navmesh.
+pub fn wall_pieces(maze: &MazeLayout, grid: MazeGrid, wall_height: f32) -> Vec<WallPiece> {
+    let mut ms = WallLayout::new(grid, wall_height);
 
     // Draw the top row
 
@@ -159,103 +467,87 @@ fn spawn_walls(
         ms.draw_post(i, maze.height);
     }
     ms.draw_post(maze.width, maze.height);
-}
-struct WallSpawner<'w, 'c> {
-    height: usize,
-    width: usize,
-    commands: Commands<'w, 'c>,
-    wall_scene: Handle<Scene>,
-    post_scene: Handle<Scene>,
+    ms.pieces
 }
 
-impl<'w, 'c> WallSpawner<'w, 'c> {
-    pub fn new(
-        commands: Commands<'w, 'c>,
-        height: usize,
-        width: usize,
-        assets: ResMut<AssetServer>,
-    ) -> WallSpawner<'w, 'c> {
-        let wall_scene = assets.load("Walls.gltf#Scene3");
-        let post_scene = assets.load("Walls.gltf#Scene2");
-        WallSpawner {
-            height,
-            width,
-            commands,
-            wall_scene,
-            post_scene,
-        }
-    }
-    fn draw_post(&mut self, x: usize, y: usize) {
-        let maze_width = self.width as f32 * (wall_length + wall_thickness) + wall_thickness;
-        let maze_height = self.height as f32 * (wall_length + wall_thickness) + wall_thickness;
-        self.commands
+fn spawn_walls(commands: &mut Commands, assets: &AssetServer, walls: Vec<WallPiece>) {
+    let wall_scene = assets.load("Walls.gltf#Scene3");
+    let post_scene = assets.load("Walls.gltf#Scene2");
+    for wall in walls {
+        commands
             .spawn(SceneBundle {
-                scene: self.post_scene.clone(),
-                transform: Transform::from_xyz(
-                    maze_width / -2.0
-                        + x as f32 * (wall_thickness + wall_length)
-                        + wall_thickness * 0.5,
-                    wall_height / 2.0,
-                    maze_height / -2.0
-                        + (wall_thickness + wall_length) * y as f32
-                        + wall_thickness * 0.5,
-                ),
+                scene: if wall.post {
+                    post_scene.clone()
+                } else {
+                    wall_scene.clone()
+                },
+                transform: wall.transform,
                 ..Default::default()
             })
             .insert(RigidBody::Static)
-            .insert(NavMeshAffector)
             .insert(Wall)
-            .insert(Collider::cuboid(
-                wall_thickness,
-                wall_height,
-                wall_thickness,
-            ));
+            .insert(LevelEntity)
+            .insert(wall.collider);
+    }
+}
+struct WallLayout {
+    grid: MazeGrid,
+    wall_height: f32,
+    pieces: Vec<WallPiece>,
+}
+
+impl WallLayout {
+    pub fn new(grid: MazeGrid, wall_height: f32) -> WallLayout {
+        WallLayout {
+            grid,
+            wall_height,
+            pieces: Vec::new(),
+        }
+    }
+    fn draw_post(&mut self, x: usize, y: usize) {
+        self.pieces.push(WallPiece {
+            transform: Transform::from_translation(
+                self.grid.corner((x, y), self.wall_height / 2.0),
+            ),
+            collider: Collider::cuboid(
+                self.grid.wall_thickness,
+                self.wall_height,
+                self.grid.wall_thickness,
+            ),
+            post: true,
+        });
     }
     fn draw_horizontal_wall(&mut self, x: usize, y: usize) {
-        let maze_width = self.width as f32 * (wall_length + wall_thickness) + wall_thickness;
-        let maze_height = self.height as f32 * (wall_length + wall_thickness) + wall_thickness;
-        self.commands
-            .spawn(SceneBundle {
-                scene: self.wall_scene.clone(),
-                transform: Transform::from_xyz(
-                    maze_width / -2.0
-                        + wall_thickness
-                        + x as f32 * (wall_thickness + wall_length)
-                        + wall_length / 2.0,
-                    wall_height / 2.0,
-                    maze_height / -2.0
-                        + (wall_thickness + wall_length) * y as f32
-                        + wall_thickness * 0.5,
-                ),
-                ..Default::default()
-            })
-            .insert(RigidBody::Static)
-            .insert(Wall)
-            .insert(NavMeshAffector)
-            .insert(Collider::cuboid(wall_length, wall_height, wall_thickness));
+        // Runs along X from the post at (x, y) to the one at (x + 1, y).
+        let position = self.grid.corner((x, y), self.wall_height / 2.0)
+            + Vec3::X * self.grid.cell_size() / 2.0;
+        self.pieces.push(WallPiece {
+            transform: Transform::from_translation(position),
+            collider: Collider::cuboid(
+                self.grid.wall_length,
+                self.wall_height,
+                self.grid.wall_thickness,
+            ),
+            post: false,
+        });
     }
     pub fn draw_vertical_wall(&mut self, x: usize, y: usize) {
-        let maze_width = self.width as f32 * (wall_length + wall_thickness) + wall_thickness;
-        let maze_height = self.height as f32 * (wall_length + wall_thickness) + wall_thickness;
-        self.commands
-            .spawn(SceneBundle {
-                scene: self.wall_scene.clone(),
-                transform: Transform::from_xyz(
-                    maze_width / -2.0
-                        + x as f32 * (wall_thickness + wall_length)
-                        + wall_thickness * 0.5,
-                    wall_height / 2.0,
-                    maze_height / -2.0
-                        + wall_length / 2.0
-                        + wall_thickness
-                        + (wall_thickness + wall_length) * y as f32,
-                )
-                .with_rotation(Quat::from_euler(EulerRot::XYZ, 0.0, PI / 2.0, 0.0)),
-                ..Default::default()
-            })
-            .insert(RigidBody::Static)
-            .insert(Wall)
-            .insert(NavMeshAffector)
-            .insert(Collider::cuboid(wall_length, wall_height, wall_thickness));
+        // Runs along Z from the post at (x, y) to the one at (x, y + 1).
+        let position = self.grid.corner((x, y), self.wall_height / 2.0)
+            + Vec3::Z * self.grid.cell_size() / 2.0;
+        self.pieces.push(WallPiece {
+            transform: Transform::from_translation(position).with_rotation(Quat::from_euler(
+                EulerRot::XYZ,
+                0.0,
+                PI / 2.0,
+                0.0,
+            )),
+            collider: Collider::cuboid(
+                self.grid.wall_length,
+                self.wall_height,
+                self.grid.wall_thickness,
+            ),
+            post: false,
+        });
     }
 }
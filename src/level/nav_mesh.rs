@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+use oxidized_navigation::conversion::{ColliderType, GeometryCollection, GeometryToConvert};
+use oxidized_navigation::{build_tile_sync, Area, NavMesh, NavMeshSettings};
+
+use super::{MazeGrid, WallPiece};
+
+/// Room left between the outside walls and the edge of the navmesh.
+const MARGIN: f32 = 2.0;
+
+/// Navmesh settings for a maze laid out on `grid`, with a single tile big
+/// enough for the whole maze.  Tiles built outside oxidized_navigation's
+/// plugin can't be linked up to their neighbours, so there is only one.
+pub fn nav_mesh_settings(grid: &MazeGrid) -> NavMeshSettings {
+    let cell_width = 0.325;
+    let world_half_extents = grid.origin().abs().max_element() + MARGIN;
+    NavMeshSettings {
+        cell_width,
+        cell_height: 0.1625,
+        tile_width: (2.0 * world_half_extents / cell_width).ceil() as u16,
+        world_half_extents,
+        world_bottom_bound: -1.0,
+        max_traversable_slope_radians: (39.9_f32).to_radians(),
+        walkable_height: 20,
+        walkable_radius: 1,
+        step_height: 3,
+        min_region_area: 1,
+        merge_region_area: 500,
+        max_edge_length: 200,
+        max_contour_simplification_error: 1.1,
+        max_tile_generation_tasks: None,
+    }
+}
+
+/// Builds the navmesh over the maze's floor and `walls` right away.
+/// oxidized_navigation's plugin builds tiles in background tasks, so
+/// enemies could get different paths from one run of a level to the next;
+/// this one is done before the first tick and is the same every time.
+pub fn build_nav_mesh(grid: &MazeGrid, walls: &[WallPiece]) -> (NavMeshSettings, NavMesh) {
+    let settings = nav_mesh_settings(grid);
+    let size = grid.origin().abs() * 2.0;
+    let floor = Collider::cuboid(size.x, 0.01, size.y);
+    let geometry = std::iter::once((&Transform::IDENTITY, &floor))
+        .chain(walls.iter().map(|wall| (&wall.transform, &wall.collider)))
+        .filter_map(|(transform, collider)| {
+            let cuboid = collider.shape().as_cuboid()?;
+            Some(GeometryCollection {
+                transform: *transform,
+                geometry_to_convert: GeometryToConvert::Collider(ColliderType::Cuboid(*cuboid)),
+                // What the plugin gives anything not marked otherwise.
+                area: Some(Area(0)),
+            })
+        })
+        .collect();
+    let tile = build_tile_sync(geometry, UVec2::ZERO, Box::default(), &settings);
+    let nav_mesh = NavMesh::default();
+    nav_mesh
+        .get()
+        .write()
+        .expect("a new navmesh is not shared yet")
+        .tiles
+        .insert(UVec2::ZERO, tile);
+    (settings, nav_mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use oxidized_navigation::query::find_path;
+
+    use super::*;
+    use crate::level::{wall_pieces, LevelConfig, MazeLayout};
+
+    #[test]
+    fn paths_go_round_the_walls() {
+        // A U: down column 0, along row 2 and back up column 1.
+        let mut maze = MazeLayout::closed(2, 3);
+        maze.remove_wall((0, 0), (0, 1));
+        maze.remove_wall((0, 1), (0, 2));
+        maze.remove_wall((0, 2), (1, 2));
+        maze.remove_wall((1, 2), (1, 1));
+        maze.remove_wall((1, 1), (1, 0));
+        let config = LevelConfig::default();
+        let grid = MazeGrid::new(2, 3, &config);
+        let (settings, nav_mesh) =
+            build_nav_mesh(&grid, &wall_pieces(&maze, grid, config.wall_height));
+
+        let tiles = nav_mesh.get();
+        let tiles = tiles.read().unwrap();
+        let from = grid.cell_center((0, 0), 0.0);
+        let to = grid.cell_center((1, 0), 0.0);
+        let path = find_path(&tiles, &settings, from, to, None, None).unwrap();
+        assert!(path.first().unwrap().xz().distance(from.xz()) < 0.5);
+        assert!(path.last().unwrap().xz().distance(to.xz()) < 0.5);
+        // It goes round the end of the wall between the two columns rather
+        // than through it.
+        let wall_end = grid.corner((1, 2), 0.0).z - grid.wall_thickness / 2.0;
+        assert!(path.iter().any(|point| point.z >= wall_end));
+    }
+}
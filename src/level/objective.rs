@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::layout::Cell;
+use super::{LevelCleared, LevelEntity, MazeGrid, WaveSpawner};
+use crate::enemy::EnemyCounts;
+use crate::player::Player;
+
+/// What the player has to do to clear a level.
+#[derive(Resource, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Objective {
+    /// Kill every enemy in the maze.
+    #[default]
+    KillAll,
+    /// Walk onto the exit, placed in the cell farthest from the start.  It
+    /// stays hidden and locked until `kills_needed` enemies are dead.
+    ReachExit { kills_needed: usize },
+    /// Stay alive against endless waves.  The level is never cleared.
+    Survival,
+}
+
+/// Kills still needed before the exit opens.  Once no more waves are due,
+/// never more than there are enemies left to kill.
+fn kills_left(
+    kills_needed: usize,
+    enemy_counts: &EnemyCounts,
+    spawner: Option<&WaveSpawner>,
+) -> usize {
+    let needed = if spawner.is_none_or(|spawner| spawner.is_finished()) {
+        kills_needed.min(enemy_counts.count as usize)
+    } else {
+        kills_needed
+    };
+    needed.saturating_sub(enemy_counts.killed as usize)
+}
+
+impl Objective {
+    /// A line for the HUD describing what is left to do.
+    pub fn describe(&self, enemy_counts: &EnemyCounts, spawner: Option<&WaveSpawner>) -> String {
+        match *self {
+            Objective::KillAll => String::from("Kill every enemy"),
+            Objective::ReachExit { kills_needed }
+                if kills_left(kills_needed, enemy_counts, spawner) > 0 =>
+            {
+                format!(
+                    "Kill {} more to open the exit",
+                    kills_left(kills_needed, enemy_counts, spawner)
+                )
+            }
+            Objective::ReachExit { .. } => String::from("The exit is open!"),
+            Objective::Survival => String::from("Survive as long as you can"),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Exit {
+    cell: Cell,
+    locked: bool,
+}
+
+pub fn spawn_exit(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    grid: &MazeGrid,
+    cell: Cell,
+) {
+    let size = grid.wall_length * 0.8;
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(shape::Box::new(size, 0.1, size).into()),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.2, 0.6, 1.0),
+                emissive: Color::rgb(0.1, 0.4, 1.0),
+                ..default()
+            }),
+            transform: Transform::from_translation(grid.cell_center(cell, 0.05)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Exit { cell, locked: true },
+        LevelEntity,
+    ));
+}
+
+pub fn unlock_exit(
+    objective: Res<Objective>,
+    enemy_counts: Res<EnemyCounts>,
+    spawner: Option<Res<WaveSpawner>>,
+    mut query: Query<(&mut Exit, &mut Visibility)>,
+) {
+    if let Objective::ReachExit { kills_needed } = *objective {
+        for (mut exit, mut visibility) in query.iter_mut() {
+            if exit.locked && kills_left(kills_needed, &enemy_counts, spawner.as_deref()) == 0 {
+                exit.locked = false;
+                *visibility = Visibility::Visible;
+            }
+        }
+    }
+}
+
+pub fn reach_exit(
+    grid: Res<MazeGrid>,
+    exit_query: Query<&Exit>,
+    player_query: Query<&Transform, With<Player>>,
+    mut cleared: EventWriter<LevelCleared>,
+) {
+    for exit in exit_query.iter() {
+        let reached = player_query
+            .iter()
+            .any(|xform| grid.cell_at(xform.translation) == Some(exit.cell));
+        if !exit.locked && reached {
+            cleared.send(LevelCleared);
+        }
+    }
+}
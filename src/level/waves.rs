@@ -0,0 +1,192 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use super::config::EnemyWeight;
+use super::layout::Cell;
+use super::{choose_enemy_kind, MazeGrid};
+use crate::enemy::{self, Dying, Enemy, EnemyCounts, EnemyKinds};
+use crate::player::Player;
+
+/// How much bigger each repeat of the last wave gets in endless mode, as a
+/// fraction of its original size.
+const ENDLESS_WAVE_GROWTH: f32 = 0.25;
+
+/// A group of enemies released together.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Wave {
+    /// Seconds after the previous wave, or the start of the level, that
+    /// this one starts.
+    pub delay: f32,
+    pub enemies: usize,
+    /// What the wave is made of.  Left empty, the level's enemy table is
+    /// used.
+    #[serde(default)]
+    pub enemy_table: Vec<EnemyWeight>,
+}
+
+/// Settings for releasing a level's enemies in waves instead of all at
+/// once.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveConfig {
+    pub waves: Vec<Wave>,
+    /// Enemies never appear closer to any player than this.
+    pub min_player_distance: f32,
+    /// Most enemies alive at once.  The rest of a wave waits for room.
+    pub max_alive: usize,
+    /// Keep repeating the last wave, a little bigger each time, once the
+    /// list runs out.
+    #[serde(default)]
+    pub endless: bool,
+}
+
+impl Default for WaveConfig {
+    /// The waves used for survival mode when the config doesn't give any.
+    fn default() -> Self {
+        WaveConfig {
+            waves: vec![
+                Wave {
+                    delay: 3.0,
+                    enemies: 5,
+                    enemy_table: Vec::new(),
+                },
+                Wave {
+                    delay: 20.0,
+                    enemies: 10,
+                    enemy_table: Vec::new(),
+                },
+                Wave {
+                    delay: 20.0,
+                    enemies: 15,
+                    enemy_table: Vec::new(),
+                },
+            ],
+            min_player_distance: 15.0,
+            max_alive: 40,
+            endless: true,
+        }
+    }
+}
+
+/// Releases the current level's enemies wave by wave from spawn points in
+/// the maze's dead ends.
+#[derive(Resource)]
+pub struct WaveSpawner {
+    config: WaveConfig,
+    /// The level's own enemy table, for waves that don't have one.
+    enemy_table: Vec<EnemyWeight>,
+    spawn_points: Vec<Cell>,
+    /// How many waves have started.
+    pub wave: usize,
+    next_wave_at: f32,
+    /// Enemies from started waves still waiting to be placed.
+    pending: usize,
+    pending_table: Vec<EnemyWeight>,
+    rng: StdRng,
+}
+
+impl WaveSpawner {
+    pub fn new(
+        config: WaveConfig,
+        enemy_table: Vec<EnemyWeight>,
+        spawn_points: Vec<Cell>,
+        now: f32,
+        rng: StdRng,
+    ) -> Self {
+        let next_wave_at = now + config.waves.first().map_or(0.0, |wave| wave.delay);
+        WaveSpawner {
+            config,
+            enemy_table,
+            spawn_points,
+            wave: 0,
+            next_wave_at,
+            pending: 0,
+            pending_table: Vec::new(),
+            rng,
+        }
+    }
+
+    /// True once every wave has started and been fully placed.
+    pub fn is_finished(&self) -> bool {
+        !self.config.endless && self.wave >= self.config.waves.len() && self.pending == 0
+    }
+
+    /// The wave to start next, sized up for repeats in endless mode.
+    fn upcoming(&self) -> Option<Wave> {
+        let waves = &self.config.waves;
+        match waves.get(self.wave) {
+            Some(wave) => Some(wave.clone()),
+            None if self.config.endless => {
+                let last = waves.last()?;
+                let repeats = (self.wave + 1 - waves.len()) as f32;
+                Some(Wave {
+                    enemies: (last.enemies as f32 * (1.0 + ENDLESS_WAVE_GROWTH * repeats)) as usize,
+                    ..last.clone()
+                })
+            }
+            None => None,
+        }
+    }
+}
+
+/// Starts waves when they're due and places their enemies at spawn points
+/// far enough from every player, as long as there's room under the cap.
+#[allow(clippy::too_many_arguments)]
+pub fn run_waves(
+    mut commands: Commands,
+    assets: ResMut<AssetServer>,
+    time: Res<Time>,
+    spawner: Option<ResMut<WaveSpawner>>,
+    kinds: EnemyKinds,
+    grid: Res<MazeGrid>,
+    player_query: Query<&Transform, With<Player>>,
+    alive: Query<(), (With<Enemy>, Without<Dying>)>,
+    mut enemy_counts: ResMut<EnemyCounts>,
+) {
+    let Some(mut spawner) = spawner else {
+        return;
+    };
+    let now = time.elapsed_seconds();
+    if now >= spawner.next_wave_at && spawner.pending == 0 {
+        if let Some(wave) = spawner.upcoming() {
+            spawner.wave += 1;
+            spawner.pending = wave.enemies;
+            spawner.pending_table = if wave.enemy_table.is_empty() {
+                spawner.enemy_table.clone()
+            } else {
+                wave.enemy_table
+            };
+            enemy_counts.count += wave.enemies as i32;
+            let delay = spawner.upcoming().map_or(0.0, |next| next.delay);
+            spawner.next_wave_at = now + delay;
+        }
+    }
+
+    let far_enough: Vec<Cell> = spawner
+        .spawn_points
+        .iter()
+        .cloned()
+        .filter(|&cell| {
+            player_query.iter().all(|xform| {
+                let player_pos = xform.translation;
+                grid.cell_center(cell, player_pos.y).distance(player_pos)
+                    >= spawner.config.min_player_distance
+            })
+        })
+        .collect();
+    let room = spawner
+        .config
+        .max_alive
+        .saturating_sub(alive.iter().count());
+    let spawner = &mut *spawner;
+    for _ in 0..room.min(spawner.pending) {
+        let Some(&cell) = far_enough.choose(&mut spawner.rng) else {
+            break;
+        };
+        let kind = choose_enemy_kind(&spawner.pending_table, &kinds, &mut spawner.rng);
+        let position = grid.cell_center(cell, 0.0);
+        enemy::spawn_enemy(&mut commands, &assets, now, &kind, position.x, position.z);
+        spawner.pending -= 1;
+    }
+}
@@ -0,0 +1,18 @@
+pub mod bindings;
+pub mod bullet;
+pub mod camera;
+pub mod character_controller;
+pub mod controller;
+pub mod enemy;
+pub mod fps;
+pub mod gameplay;
+pub mod health;
+pub mod input;
+pub mod interpolation;
+pub mod level;
+pub mod menu;
+pub mod player;
+pub mod replay;
+pub mod ron_loader;
+pub mod state;
+pub mod stats;
@@ -1,68 +1,75 @@
-use bevy::{
-    core_pipeline::core_3d::Camera3dDepthLoadOp, diagnostic::FrameTimeDiagnosticsPlugin, prelude::*,
-};
-use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use bevy_xpbd_3d::{plugins::debug::PhysicsDebugPlugin, prelude::*};
-use fps::ShowFps;
+use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*};
 use leafwing_input_manager::prelude::InputManagerPlugin;
-use oxidized_navigation::{NavMeshAffector, NavMeshSettings, OxidizedNavigationPlugin};
-mod bullet;
-mod character_controller;
-mod enemy;
-mod fps;
-mod input;
-mod level;
-mod player;
+use maze_shooter::controller::ControllerSet;
+use maze_shooter::fps::ShowFps;
+use maze_shooter::state::{self, GameState};
+use maze_shooter::{
+    bindings, bullet, camera, enemy, gameplay, input, interpolation, level, menu, player, replay,
+    ron_loader,
+};
 
 fn main() {
     App::new()
         .insert_resource(ShowFps(false))
-        .insert_resource(enemy::EnemyCounts {
-            count: enemy::enemies_to_spawn,
-            killed: 0,
-        })
+        .init_resource::<enemy::ShowAiDebug>()
+        .init_resource::<input::AimMode>()
+        .init_resource::<camera::CameraMode>()
+        .init_resource::<input::LocalPlayers>()
         .add_plugins((
             DefaultPlugins,
             //WorldInspectorPlugin::new(),
             FrameTimeDiagnosticsPlugin,
+            state::GameStatePlugin,
+            menu::MenuPlugin,
             level::LevelPlugin,
+            replay::ReplayPlugin,
             InputManagerPlugin::<input::Action>::default(),
-            OxidizedNavigationPlugin::<Collider>::new(self::NavMeshSettings {
-                cell_width: 0.325,
-                cell_height: 0.1625,
-                tile_width: 100,
-                world_half_extents: 160.0,
-                world_bottom_bound: -1.0,
-                max_traversable_slope_radians: (39.9_f32).to_radians(),
-                walkable_height: 20,
-                walkable_radius: 1,
-                step_height: 3,
-                min_region_area: 1,
-                merge_region_area: 500,
-                max_edge_length: 200,
-                max_contour_simplification_error: 1.1,
-                max_tile_generation_tasks: Some(100),
-            }),
-            oxidized_navigation::debug_draw::OxidizedNavigationDebugDrawPlugin,
-            PhysicsPlugins::default(),
-            character_controller::CharacterControllerPlugin,
+            gameplay::GameplayPlugin,
         ))
+        .init_asset::<enemy::EnemyKind>()
+        .register_asset_loader(ron_loader::RonLoader::<enemy::EnemyKind>::new(&[
+            "enemy.ron",
+        ]))
         .add_systems(
             Startup,
-            (player::spawn_player_ui, enemy::setup_walk_animation),
+            (
+                bindings::load_bindings,
+                enemy::setup_walk_animation,
+                enemy::load_enemy_kinds,
+                bullet::setup_impact_effect,
+            ),
         )
         .add_systems(
             Update,
             (
-                bullet::hit_bullet,
-                enemy::move_enemy,
-                enemy::start_walk_animation,
-                input::fire_gun,
-                input::move_camera,
-                input::move_player,
+                bullet::fade_impacts,
+                camera::move_camera.after(interpolation::interpolate_transforms),
+                camera::move_player_cameras.after(interpolation::interpolate_transforms),
+                enemy::flash_enemies,
+                input::read_input.in_set(ControllerSet),
+                player::flash_player,
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (
+                enemy::draw_ai_debug,
+                input::toggle_ai_debug,
                 input::toggle_fps,
-                player::regen_ammo,
+                input::toggle_pause,
                 player::update_player_ui,
+            )
+                .run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))),
+        )
+        .add_systems(
+            Update,
+            (
+                camera::add_player_cameras,
+                camera::set_viewports,
+                enemy::start_walk_animation,
+                enemy::start_death_animation,
+                player::tint_players,
             ),
         )
         .run();
@@ -0,0 +1,469 @@
+use bevy::prelude::*;
+use leafwing_input_manager::user_input::InputKind;
+
+use crate::bindings::{describe, Bindings, Displaced, REBINDABLE};
+use crate::camera::CameraMode;
+use crate::input::{AimMode, LocalPlayers};
+use crate::level::{self, CampaignProgress, GameMode, LevelSeed, NextMaze, WaveSpawner};
+use crate::state::{despawn_with, GameState};
+use crate::stats::RunStats;
+
+/// Most players the title screen lets you pick.
+const MAX_LOCAL_PLAYERS: usize = 4;
+
+pub struct MenuPlugin;
+
+#[derive(Component)]
+struct TitleScreen;
+#[derive(Component)]
+struct PauseScreen;
+#[derive(Component)]
+struct ResultsScreen;
+#[derive(Component)]
+struct ControlsScreen;
+
+/// Where the controls menu is: the highlighted action, whether it is
+/// waiting for an input to bind to it, and what happened last.
+#[derive(Resource, Default)]
+struct ControlsMenu {
+    selected: usize,
+    capturing: bool,
+    message: String,
+}
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlsMenu>()
+            .add_systems(OnEnter(GameState::Title), spawn_title_screen)
+            .add_systems(OnExit(GameState::Title), despawn_with::<TitleScreen>)
+            .add_systems(OnEnter(GameState::Controls), spawn_controls_screen)
+            .add_systems(OnExit(GameState::Controls), despawn_with::<ControlsScreen>)
+            .add_systems(OnEnter(GameState::Paused), spawn_pause_screen)
+            .add_systems(OnExit(GameState::Paused), despawn_with::<PauseScreen>)
+            .add_systems(OnEnter(GameState::Victory), spawn_victory_screen)
+            .add_systems(OnExit(GameState::Victory), despawn_with::<ResultsScreen>)
+            .add_systems(OnEnter(GameState::GameOver), spawn_game_over_screen)
+            .add_systems(OnExit(GameState::GameOver), despawn_with::<ResultsScreen>)
+            .add_systems(
+                Update,
+                (
+                    // Starting a level before its config has loaded would
+                    // build it from the defaults.
+                    title_input
+                        .run_if(in_state(GameState::Title).and_then(level::level_assets_loaded)),
+                    controls_input.run_if(in_state(GameState::Controls)),
+                    pause_input.run_if(in_state(GameState::Paused)),
+                    victory_input.run_if(in_state(GameState::Victory)),
+                    game_over_input.run_if(in_state(GameState::GameOver)),
+                ),
+            );
+    }
+}
+
+fn confirm_pressed(keys: &Input<KeyCode>, buttons: &Input<GamepadButton>) -> bool {
+    keys.any_just_pressed([KeyCode::Return, KeyCode::Space])
+        || buttons.get_just_pressed().any(|b| {
+            matches!(
+                b.button_type,
+                GamepadButtonType::South | GamepadButtonType::Start
+            )
+        })
+}
+
+/// Spawns a full-screen, centered overlay holding a single block of text.
+fn spawn_screen(commands: &mut Commands, marker: impl Component, text: &str) {
+    spawn_screen_sized(commands, marker, text, 40.0);
+}
+
+fn spawn_screen_sized(commands: &mut Commands, marker: impl Component, text: &str, font_size: f32) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+            marker,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size,
+                        ..default()
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center),
+            );
+        });
+}
+
+fn spawn_title_screen(
+    mut commands: Commands,
+    players: Res<LocalPlayers>,
+    camera_mode: Res<CameraMode>,
+) {
+    spawn_screen(
+        &mut commands,
+        TitleScreen,
+        &title_text(*players, *camera_mode),
+    );
+}
+
+fn title_text(players: LocalPlayers, camera_mode: CameraMode) -> String {
+    let split = match camera_mode {
+        CameraMode::Shared => "off",
+        CameraMode::SplitScreen => "on",
+    };
+    format!(
+        "MAZE SHOOTER\n\nPress Enter to start\nS for survival mode\nP for players: {}\nV for split screen: {}\nC for controls",
+        players.0, split
+    )
+}
+
+fn spawn_pause_screen(mut commands: Commands, aim_mode: Res<AimMode>) {
+    spawn_screen(&mut commands, PauseScreen, &pause_text(*aim_mode));
+}
+
+fn pause_text(aim_mode: AimMode) -> String {
+    let aim = match aim_mode {
+        AimMode::Keys => "arrow keys",
+        AimMode::Mouse => "mouse",
+    };
+    format!(
+        "Paused\n\nEsc to resume\nM to switch aiming (now: {})\nQ to quit to title",
+        aim
+    )
+}
+
+fn results_text(
+    heading: &str,
+    options: &str,
+    stats: &RunStats,
+    time: &Time,
+    seed: &LevelSeed,
+) -> String {
+    format!(
+        "{}\n\nTime: {:.1} s\nShots fired: {}\nAccuracy: {:.0}%\nSeed: {}\n\n{}",
+        heading,
+        time.elapsed_seconds() - stats.started,
+        stats.shots_fired,
+        stats.accuracy() * 100.0,
+        seed.0,
+        options,
+    )
+}
+
+fn spawn_victory_screen(
+    mut commands: Commands,
+    stats: Res<RunStats>,
+    time: Res<Time>,
+    seed: Res<LevelSeed>,
+    progress: Res<CampaignProgress>,
+) {
+    let text = if progress.is_last_level() {
+        results_text(
+            "Campaign complete!",
+            "Enter: back to title",
+            &stats,
+            &time,
+            &seed,
+        )
+    } else {
+        results_text(
+            &format!("Level {} cleared!", progress.level + 1),
+            "Enter: next level\nR: retry\nQ: quit to title",
+            &stats,
+            &time,
+            &seed,
+        )
+    };
+    spawn_screen(&mut commands, ResultsScreen, &text);
+}
+
+fn spawn_game_over_screen(
+    mut commands: Commands,
+    stats: Res<RunStats>,
+    time: Res<Time>,
+    seed: Res<LevelSeed>,
+    mode: Res<GameMode>,
+    spawner: Option<Res<WaveSpawner>>,
+) {
+    let heading = match (*mode, spawner) {
+        (GameMode::Survival, Some(spawner)) => {
+            format!("Game over\nReached wave {}", spawner.wave)
+        }
+        _ => String::from("Game over"),
+    };
+    let text = results_text(
+        &heading,
+        "Enter: retry\nQ: quit to title",
+        &stats,
+        &time,
+        &seed,
+    );
+    spawn_screen(&mut commands, ResultsScreen, &text);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn title_input(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut mode: ResMut<GameMode>,
+    mut players: ResMut<LocalPlayers>,
+    mut camera_mode: ResMut<CameraMode>,
+    screens: Query<Entity, With<TitleScreen>>,
+) {
+    if confirm_pressed(&keys, &buttons) {
+        *mode = GameMode::Campaign;
+        next_state.set(GameState::Playing);
+        return;
+    } else if keys.just_pressed(KeyCode::S)
+        || buttons
+            .get_just_pressed()
+            .any(|b| b.button_type == GamepadButtonType::North)
+    {
+        *mode = GameMode::Survival;
+        next_state.set(GameState::Playing);
+        return;
+    } else if keys.just_pressed(KeyCode::C) {
+        next_state.set(GameState::Controls);
+        return;
+    } else if keys.just_pressed(KeyCode::P)
+        || buttons
+            .get_just_pressed()
+            .any(|b| b.button_type == GamepadButtonType::West)
+    {
+        players.0 = players.0 % MAX_LOCAL_PLAYERS + 1;
+    } else if keys.just_pressed(KeyCode::V) {
+        *camera_mode = camera_mode.toggled();
+    } else {
+        return;
+    }
+    // Redraw the screen to show the new settings.
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+    spawn_screen(
+        &mut commands,
+        TitleScreen,
+        &title_text(*players, *camera_mode),
+    );
+}
+
+fn spawn_controls_screen(
+    mut commands: Commands,
+    mut menu: ResMut<ControlsMenu>,
+    bindings: Res<Bindings>,
+) {
+    *menu = ControlsMenu::default();
+    spawn_screen_sized(
+        &mut commands,
+        ControlsScreen,
+        &controls_text(&menu, &bindings),
+        24.0,
+    );
+}
+
+fn controls_text(menu: &ControlsMenu, bindings: &Bindings) -> String {
+    let mut text = String::from("Controls\n\n");
+    for (i, &action) in REBINDABLE.iter().enumerate() {
+        let inputs: Vec<String> = bindings.inputs(action).map(describe).collect();
+        let marker = if i == menu.selected { ">" } else { " " };
+        text += &format!("{} {}: {}\n", marker, action.label(), inputs.join(", "));
+    }
+    text += "\nUp/Down: choose  Enter: rebind  Backspace: reset all  Esc: back\n";
+    text += &menu.message;
+    text
+}
+
+/// The first key, mouse button or gamepad button pressed this frame.
+fn captured_input(
+    keys: &Input<KeyCode>,
+    mouse: &Input<MouseButton>,
+    buttons: &Input<GamepadButton>,
+) -> Option<InputKind> {
+    keys.get_just_pressed()
+        .next()
+        .map(|&key| InputKind::Keyboard(key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|&button| InputKind::Mouse(button))
+        })
+        .or_else(|| {
+            buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| InputKind::GamepadButton(button.button_type))
+        })
+}
+
+/// Moves around the controls menu, and while capturing binds the next
+/// input pressed to the highlighted action.  Every change is saved.
+#[allow(clippy::too_many_arguments)]
+fn controls_input(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut menu: ResMut<ControlsMenu>,
+    mut bindings: ResMut<Bindings>,
+    screens: Query<Entity, With<ControlsScreen>>,
+) {
+    let action = REBINDABLE[menu.selected];
+    let pad_pressed = |button_type| {
+        buttons
+            .get_just_pressed()
+            .any(|b| b.button_type == button_type)
+    };
+    if menu.capturing {
+        if keys.just_pressed(KeyCode::Escape) {
+            menu.capturing = false;
+            menu.message = String::new();
+        } else if let Some(input) = captured_input(&keys, &mouse, &buttons) {
+            menu.capturing = false;
+            menu.message = match bindings.rebind(action, input) {
+                Some(Displaced::Swapped(other)) => format!(
+                    "{} was already used for {}; swapped them",
+                    describe(input),
+                    other.label()
+                ),
+                Some(Displaced::Unbound(other)) => format!(
+                    "{} was already used for {}, which now has nothing on that device",
+                    describe(input),
+                    other.label()
+                ),
+                None => format!("{} bound to {}", action.label(), describe(input)),
+            };
+            save_bindings(&bindings, &mut menu);
+        } else {
+            return;
+        }
+    } else if keys.just_pressed(KeyCode::Escape) || pad_pressed(GamepadButtonType::East) {
+        next_state.set(GameState::Title);
+        return;
+    } else if keys.just_pressed(KeyCode::Up) || pad_pressed(GamepadButtonType::DPadUp) {
+        menu.selected = (menu.selected + REBINDABLE.len() - 1) % REBINDABLE.len();
+    } else if keys.just_pressed(KeyCode::Down) || pad_pressed(GamepadButtonType::DPadDown) {
+        menu.selected = (menu.selected + 1) % REBINDABLE.len();
+    } else if confirm_pressed(&keys, &buttons) {
+        menu.capturing = true;
+        menu.message = format!(
+            "Press a key or button for {} (Esc to cancel)",
+            action.label()
+        );
+    } else if keys.just_pressed(KeyCode::Back) {
+        *bindings = Bindings::default();
+        menu.message = String::from("Reset to defaults");
+        save_bindings(&bindings, &mut menu);
+    } else {
+        return;
+    }
+    // Redraw the screen to show the change.
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+    spawn_screen_sized(
+        &mut commands,
+        ControlsScreen,
+        &controls_text(&menu, &bindings),
+        24.0,
+    );
+}
+
+fn save_bindings(bindings: &Bindings, menu: &mut ControlsMenu) {
+    if let Err(e) = bindings.save() {
+        warn!("Couldn't save bindings: {}", e);
+        menu.message += &format!("\nCouldn't save: {}", e);
+    }
+}
+
+/// Leaves the run and goes back to the title screen, which starts the
+/// campaign over on a new maze.
+fn quit_to_title(
+    next_state: &mut NextState<GameState>,
+    next_maze: &mut NextMaze,
+    progress: &mut CampaignProgress,
+) {
+    *next_maze = NextMaze::New;
+    progress.level = 0;
+    next_state.set(GameState::Title);
+}
+
+fn retry_pressed(keys: &Input<KeyCode>, buttons: &Input<GamepadButton>) -> bool {
+    keys.just_pressed(KeyCode::R)
+        || buttons
+            .get_just_pressed()
+            .any(|b| b.button_type == GamepadButtonType::West)
+}
+
+fn pause_input(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_maze: ResMut<NextMaze>,
+    mut progress: ResMut<CampaignProgress>,
+    mut aim_mode: ResMut<AimMode>,
+    screens: Query<Entity, With<PauseScreen>>,
+) {
+    if keys.just_pressed(KeyCode::Q) {
+        quit_to_title(&mut next_state, &mut next_maze, &mut progress);
+    } else if keys.just_pressed(KeyCode::M) {
+        *aim_mode = aim_mode.toggled();
+        // Redraw the screen so it shows the new setting.
+        for screen in screens.iter() {
+            commands.entity(screen).despawn_recursive();
+        }
+        spawn_screen(&mut commands, PauseScreen, &pause_text(*aim_mode));
+    }
+}
+
+fn victory_input(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_maze: ResMut<NextMaze>,
+    mut progress: ResMut<CampaignProgress>,
+) {
+    if progress.is_last_level() {
+        if confirm_pressed(&keys, &buttons) {
+            quit_to_title(&mut next_state, &mut next_maze, &mut progress);
+        }
+    } else if confirm_pressed(&keys, &buttons) {
+        progress.level += 1;
+        *next_maze = NextMaze::New;
+        next_state.set(GameState::Playing);
+    } else if retry_pressed(&keys, &buttons) {
+        *next_maze = NextMaze::Retry;
+        next_state.set(GameState::Playing);
+    } else if keys.just_pressed(KeyCode::Q) {
+        quit_to_title(&mut next_state, &mut next_maze, &mut progress);
+    }
+}
+
+fn game_over_input(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_maze: ResMut<NextMaze>,
+    mut progress: ResMut<CampaignProgress>,
+) {
+    if confirm_pressed(&keys, &buttons) || retry_pressed(&keys, &buttons) {
+        *next_maze = NextMaze::Retry;
+        next_state.set(GameState::Playing);
+    } else if keys.just_pressed(KeyCode::Q) {
+        quit_to_title(&mut next_state, &mut next_maze, &mut progress);
+    }
+}
@@ -1,94 +1,314 @@
+#![allow(non_upper_case_globals)]
+
+use crate::bindings::Bindings;
 use crate::character_controller as cc;
-use crate::enemy::EnemyCounts;
+use crate::controller::{Controller, Intent};
+use crate::enemy::{Dying, Enemy, EnemyCounts};
 use crate::fps::ShowFps;
-use crate::input;
+use crate::health::Health;
+use crate::input::{self, InputDevices};
+use crate::interpolation::Interpolated;
+use crate::level::{CampaignProgress, LevelEntity, LevelSeed, Objective, WaveSpawner};
 use bevy::diagnostic::DiagnosticsStore;
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 use bevy::prelude::*;
 use bevy_xpbd_3d::prelude::*;
 
-const turn_speed: f32 = 10.0 * 2.0 * std::f32::consts::PI / 360.0;
 const max_ammunition: usize = 6;
-const regen_time: f32 = 1.0;
+const max_health: i32 = 5;
+const invulnerable_time: f32 = 1.5;
+const knockback_speed: f32 = 15.0;
+/// Robot colours for each local player, in join order.
+const player_tints: [Color; 4] = [
+    Color::WHITE,
+    Color::rgb(1.0, 0.55, 0.55),
+    Color::rgb(0.55, 0.7, 1.0),
+    Color::rgb(1.0, 0.9, 0.45),
+];
+/// How far apart players start when there are several.
+const spawn_spread: f32 = 1.0;
 #[derive(Component)]
 pub struct Player {
+    /// Which local player this is, counting from 0.
+    pub index: usize,
     pub ammunition: usize,
     pub last_shot_time: Option<f32>,
+    pub last_hit_time: Option<f32>,
     pub aim: f32,
+    /// Seconds between regenerated bullets.
+    pub regen_time: f32,
+}
+/// The level-wide part of the HUD: progress, objective and seed.
+#[derive(Component)]
+pub struct LevelUi;
+/// One player's HUD panel.
+#[derive(Component)]
+pub struct PlayerUi {
+    pub player: Entity,
+    pub index: usize,
+}
+/// Colour multiplied into every material of a robot's model.
+#[derive(Component)]
+pub struct Tint(pub Color);
+/// Sent when the last player standing goes down.
+#[derive(Event)]
+pub struct PlayerDied;
+/// Sent when an enemy projectile hits a player.  `from` is where it came
+/// from, for knockback.
+#[derive(Event)]
+pub struct PlayerShot {
+    pub player: Entity,
+    pub damage: i32,
+    pub from: Vec3,
 }
 
-pub fn spawn_player(mut commands: &mut Commands, assets: &ResMut<AssetServer>, x: f32, y: f32) {
+/// Spawns local player number `index` of `count` around `(x, y)`, reading
+/// input from `devices`.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_player(
+    commands: &mut Commands,
+    assets: &ResMut<AssetServer>,
+    x: f32,
+    y: f32,
+    regen_time: f32,
+    bindings: &Bindings,
+    index: usize,
+    count: usize,
+    devices: InputDevices,
+) -> Entity {
+    let offset = if count > 1 {
+        let angle = index as f32 / count as f32 * std::f32::consts::TAU;
+        Vec2::new(angle.cos(), angle.sin()) * spawn_spread
+    } else {
+        Vec2::ZERO
+    };
+    let transform = Transform::from_xyz(x + offset.x, 1.5, y + offset.y);
     commands
         .spawn(SceneBundle {
             scene: assets.load("Robot.gltf#Scene0"),
-            transform: Transform::from_xyz(x, 1.5, y),
+            transform,
             ..Default::default()
         })
-        .insert(input::input_bundle())
+        .insert(Interpolated::new(transform))
+        .insert(input::input_bundle(bindings, devices))
+        .insert(devices)
+        .insert(Controller::Input)
+        .insert(Intent::default())
         .insert(Player {
+            index,
             ammunition: max_ammunition,
             last_shot_time: None,
+            last_hit_time: None,
             aim: 0.0,
+            regen_time,
         })
+        .insert(Tint(player_tints[index % player_tints.len()]))
+        .insert(Health::new(max_health))
+        .insert(LevelEntity)
         .insert(LinearVelocity(Vec3::new(0.0, 0.0, 0.0)))
         .insert(cc::CharacterControllerBundle::new(
             Collider::ball(0.5),
             Vec3::new(0.0, -1.0, 0.0),
-        ));
+        ))
+        .id()
 }
+
+/// The position of whichever of `players` is closest to `from`.
+pub fn nearest_player(players: &[Vec3], from: Vec3) -> Option<Vec3> {
+    players.iter().cloned().min_by(|a, b| {
+        a.distance_squared(from)
+            .total_cmp(&b.distance_squared(from))
+    })
+}
+
+/// Gives each robot's materials its player's [`Tint`] as its scene spawns.
+/// Scenes share materials, so tinted ones are copies.
+pub fn tint_players(
+    mut commands: Commands,
+    tints: Query<&Tint>,
+    parents: Query<&Parent>,
+    meshes: Query<(Entity, &Handle<StandardMaterial>), Added<Handle<StandardMaterial>>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, handle) in meshes.iter() {
+        let Some(tint) = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| tints.get(ancestor).ok())
+        else {
+            continue;
+        };
+        if tint.0 == Color::WHITE {
+            continue;
+        }
+        let Some(mut material) = materials.get(handle).cloned() else {
+            continue;
+        };
+        let [r, g, b, a] = material.base_color.as_rgba_f32();
+        let [tr, tg, tb, _] = tint.0.as_rgba_f32();
+        material.base_color = Color::rgba(r * tr, g * tg, b * tb, a);
+        commands.entity(entity).insert(materials.add(material));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn update_player_ui(
-    mut txt_query: Query<&mut Text>,
-    player_query: Query<&Player>,
+    mut level_txt_query: Query<&mut Text, (With<LevelUi>, Without<PlayerUi>)>,
+    mut player_txt_query: Query<(&PlayerUi, &mut Text), Without<LevelUi>>,
+    player_query: Query<(&Player, &Health)>,
     diagnostics: Res<DiagnosticsStore>,
     show_fps: Res<ShowFps>,
     enemy_counts: Res<EnemyCounts>,
+    seed: Res<LevelSeed>,
+    progress: Res<CampaignProgress>,
+    objective: Res<Objective>,
+    spawner: Option<Res<WaveSpawner>>,
 ) {
-    let p = player_query.single();
-    let mut txt = txt_query.single_mut();
-    if show_fps.0 {
-        let fps;
-        if let Some(value) = diagnostics
+    for (ui, mut txt) in player_txt_query.iter_mut() {
+        txt.sections[0].value = match player_query.get(ui.player) {
+            Ok((p, health)) => format!(
+                "P{}\n{}/{} Health\n{} Bullets",
+                ui.index + 1,
+                health.current,
+                health.max,
+                p.ammunition
+            ),
+            Err(_) => format!("P{}\nDown", ui.index + 1),
+        };
+    }
+    let wave = spawner
+        .as_ref()
+        .map(|spawner| format!("\nWave {}", spawner.wave))
+        .unwrap_or_default();
+    let fps = if show_fps.0 {
+        let fps = diagnostics
             .get(FrameTimeDiagnosticsPlugin::FPS)
             .and_then(|fps| fps.smoothed())
-            .map(|smoothed| smoothed.round())
-        {
-            fps = value.to_string();
-        } else {
-            fps = String::from("Unknown");
-        }
-        txt.sections[0].value = format!(
-            "{} Bullets\n{} of {} enemies killed\n{} FPS",
-            p.ammunition, enemy_counts.killed, enemy_counts.count, fps
-        );
+            .map(|smoothed| smoothed.round().to_string())
+            .unwrap_or_else(|| String::from("Unknown"));
+        format!("\n{} FPS", fps)
     } else {
+        String::new()
+    };
+    for mut txt in level_txt_query.iter_mut() {
         txt.sections[0].value = format!(
-            "{} Bullets\n{} of {} enemies killed",
-            p.ammunition, enemy_counts.killed, enemy_counts.count,
+            "Level {}/{}\n{} of {} enemies killed\n{}{}\nSeed {}{}",
+            progress.level + 1,
+            progress.levels,
+            enemy_counts.killed,
+            enemy_counts.count,
+            objective.describe(&enemy_counts, spawner.as_deref()),
+            wave,
+            seed.0,
+            fps
         );
     }
 }
 pub fn regen_ammo(time: Res<Time>, mut query: Query<&mut Player>) {
-    let mut player = query.single_mut();
     let elapsed = time.elapsed_seconds();
-
-    if player.ammunition < max_ammunition {
-        player.last_shot_time = match player.last_shot_time {
-            None => Some(elapsed),
-            e => e,
-        };
-        if elapsed - player.last_shot_time.unwrap() > regen_time {
-            player.last_shot_time = Some(elapsed);
-            player.ammunition += 1;
+    for mut player in query.iter_mut() {
+        if player.ammunition < max_ammunition {
+            player.last_shot_time = match player.last_shot_time {
+                None => Some(elapsed),
+                e => e,
+            };
+            if elapsed - player.last_shot_time.unwrap() > player.regen_time {
+                player.last_shot_time = Some(elapsed);
+                player.ammunition += 1;
+            }
+        } else {
+            player.last_shot_time = None;
         }
+    }
+}
+/// Damages players when they touch an enemy or are shot, then keeps them
+/// invulnerable for a moment.  A player whose health runs out drops out of
+/// the level, unless it was the last one standing.
+pub fn hurt_player(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut player_query: Query<(
+        Entity,
+        &mut Player,
+        &mut Health,
+        &mut cc::Knockback,
+        &Transform,
+        &CollidingEntities,
+    )>,
+    enemy_query: Query<(&Enemy, &Transform), Without<Dying>>,
+    mut shots: EventReader<PlayerShot>,
+    mut died: EventWriter<PlayerDied>,
+) {
+    let shots: Vec<&PlayerShot> = shots.read().collect();
+    let elapsed = time.elapsed_seconds();
+    let mut downed = Vec::new();
+    let mut standing = 0;
+    for (entity, mut player, mut health, mut knockback, xform, colliders) in player_query.iter_mut()
+    {
+        let invulnerable = player
+            .last_hit_time
+            .is_some_and(|hit| elapsed - hit < invulnerable_time);
+        if !health.is_dead() && !invulnerable {
+            let shot_hits = shots
+                .iter()
+                .filter(|shot| shot.player == entity)
+                .map(|shot| (shot.damage, shot.from));
+            let contact_hits = colliders
+                .iter()
+                .filter_map(|e| enemy_query.get(*e).ok())
+                .map(|(enemy, enemy_xform)| (enemy.kind.damage, enemy_xform.translation));
+            // Only one hit lands per invulnerability window: the hardest,
+            // and of those the closest, so it doesn't depend on the order
+            // the collisions were found in.
+            let pos = xform.translation;
+            let hit = shot_hits
+                .chain(contact_hits)
+                .max_by(|(a, a_from), (b, b_from)| {
+                    a.cmp(b).then_with(|| {
+                        b_from
+                            .distance_squared(pos)
+                            .total_cmp(&a_from.distance_squared(pos))
+                    })
+                });
+            if let Some((damage, from)) = hit {
+                health.damage(damage);
+                player.last_hit_time = Some(elapsed);
+                let mut away = xform.translation - from;
+                away.y = 0.0;
+                knockback.0 = away.normalize_or_zero() * knockback_speed;
+                if health.is_dead() {
+                    downed.push(entity);
+                }
+            }
+        }
+        if !health.is_dead() {
+            standing += 1;
+        }
+    }
+    if standing == 0 && !downed.is_empty() {
+        died.send(PlayerDied);
     } else {
-        player.last_shot_time = None;
+        for entity in downed {
+            commands.entity(entity).despawn_recursive();
+        }
     }
 }
-pub fn spawn_player_ui(mut commands: Commands, query: Query<&Player>) {
+/// Blinks the robot while it is invulnerable after a hit.
+pub fn flash_player(time: Res<Time>, mut query: Query<(&Player, &mut Visibility)>) {
+    let elapsed = time.elapsed_seconds();
+    for (player, mut visibility) in query.iter_mut() {
+        *visibility = match player.last_hit_time {
+            Some(hit) if elapsed - hit < invulnerable_time && (elapsed * 10.0) as i32 % 2 == 0 => {
+                Visibility::Hidden
+            }
+            _ => Visibility::Inherited,
+        };
+    }
+}
+pub fn spawn_level_ui(commands: &mut Commands) {
     //    commands.spawn(Camera2dBundle::default());
     commands.spawn((
         TextBundle::from_section(
-            "Bullets: 6",
+            "Level 1",
             TextStyle {
                 font_size: 30.0,
                 ..default()
@@ -102,5 +322,30 @@ pub fn spawn_player_ui(mut commands: Commands, query: Query<&Player>) {
         // not button/list item text, this is necessary
         // for accessibility to treat the text accordingly.
         Label,
+        LevelUi,
+        LevelEntity,
+    ));
+}
+/// Spawns the HUD panel for local player number `index`, along the bottom
+/// of the screen in the player's colour.
+pub fn spawn_player_ui(commands: &mut Commands, player: Entity, index: usize) {
+    commands.spawn((
+        TextBundle::from_section(
+            "Bullets: 6",
+            TextStyle {
+                font_size: 30.0,
+                color: player_tints[index % player_tints.len()],
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.),
+            left: Val::Px(5. + 220. * index as f32),
+            ..default()
+        }),
+        Label,
+        PlayerUi { player, index },
+        LevelEntity,
     ));
 }
@@ -0,0 +1,422 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::controller::{Controller, ControllerSet, Intent};
+use crate::enemy::EnemyCounts;
+use crate::gameplay::GameplaySet;
+use crate::input::LocalPlayers;
+use crate::level::{self, CampaignProgress, GameMode, LevelSeed, NextMaze};
+use crate::player::Player;
+use crate::state::GameState;
+
+/// One level's worth of play: what's needed to build the same level again,
+/// and every player's [`Intent`] on every fixed tick of it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub mode: GameMode,
+    /// The campaign level that was played.
+    pub level: usize,
+    pub players: usize,
+    /// Enemies killed by the end of the recording, to check playback against.
+    pub killed: i32,
+    /// Each tick's intents, indexed by player.
+    pub ticks: Vec<Vec<Intent>>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, ron::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Records the level being played to the file given with
+/// `--record <file>`.  Only the latest level is kept.
+#[derive(Resource)]
+pub struct Recorder {
+    path: PathBuf,
+    replay: Option<Replay>,
+}
+
+/// Plays back the file given with `--replay <file>` in place of the
+/// players' input.
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    tick: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl Playback {
+    /// Whether the recording is in control of the players.
+    pub fn is_running(&self) -> bool {
+        self.started && !self.finished
+    }
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(path) = path_arg("--replay") {
+            match Replay::load(&path) {
+                Ok(replay) => {
+                    app.insert_resource(Playback {
+                        replay,
+                        tick: 0,
+                        started: false,
+                        finished: false,
+                    });
+                }
+                Err(e) => error!("Couldn't read replay {}: {}", path.display(), e),
+            }
+        }
+        if let Some(path) = path_arg("--record") {
+            app.insert_resource(Recorder { path, replay: None });
+        }
+        app.add_systems(
+            Update,
+            start_playback.run_if(
+                in_state(GameState::Title)
+                    .and_then(resource_exists::<Playback>())
+                    .and_then(level::level_assets_loaded),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                play_back.run_if(resource_exists::<Playback>()),
+                record.run_if(resource_exists::<Recorder>()),
+            )
+                .chain()
+                .in_set(ControllerSet)
+                .in_set(GameplaySet),
+        )
+        // Leaving `Playing` covers pausing as well as the level ending, so
+        // a bug can be saved by pausing as soon as it shows up.
+        .add_systems(
+            OnExit(GameState::Playing),
+            (
+                end_playback.run_if(resource_exists::<Playback>()),
+                save_recording.run_if(resource_exists::<Recorder>()),
+            ),
+        );
+    }
+}
+
+/// Reads `<flag> <file>` from the command line.
+fn path_arg(flag: &str) -> Option<PathBuf> {
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
+
+/// Skips the title screen and builds the recorded level, once everything
+/// it is built from has loaded.
+fn start_playback(
+    mut playback: ResMut<Playback>,
+    mut seed: ResMut<LevelSeed>,
+    mut next_maze: ResMut<NextMaze>,
+    mut mode: ResMut<GameMode>,
+    mut progress: ResMut<CampaignProgress>,
+    mut players: ResMut<LocalPlayers>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if playback.started {
+        return;
+    }
+    playback.started = true;
+    let replay = &playback.replay;
+    info!(
+        "Playing back {} ticks of seed {} with {} players",
+        replay.ticks.len(),
+        replay.seed,
+        replay.players
+    );
+    seed.0 = replay.seed;
+    *next_maze = NextMaze::Retry;
+    *mode = replay.mode;
+    progress.level = replay.level;
+    players.0 = replay.players;
+    next_state.set(GameState::Playing);
+}
+
+/// Feeds the players one tick of the recording.  When it runs out they go
+/// back to reading input.
+fn play_back(
+    mut playback: ResMut<Playback>,
+    enemy_counts: Res<EnemyCounts>,
+    mut query: Query<(&Player, &mut Controller, &mut Intent)>,
+) {
+    if !playback.is_running() {
+        return;
+    }
+    let tick = playback.tick;
+    playback.tick += 1;
+    let Some(intents) = playback.replay.ticks.get(tick) else {
+        for (_, mut controller, mut intent) in query.iter_mut() {
+            *controller = Controller::Input;
+            *intent = Intent::default();
+        }
+        finish(&mut playback, &enemy_counts);
+        return;
+    };
+    for (player, mut controller, mut intent) in query.iter_mut() {
+        *controller = Controller::Replay;
+        *intent = intents.get(player.index).cloned().unwrap_or_default();
+    }
+}
+
+/// Reports on a playback whose level ended with the recording.
+fn end_playback(mut playback: ResMut<Playback>, enemy_counts: Res<EnemyCounts>) {
+    if playback.is_running() && playback.tick >= playback.replay.ticks.len() {
+        finish(&mut playback, &enemy_counts);
+    }
+}
+
+fn finish(playback: &mut Playback, enemy_counts: &EnemyCounts) {
+    playback.finished = true;
+    if enemy_counts.killed == playback.replay.killed {
+        info!("Replay finished, {} enemies killed", enemy_counts.killed);
+    } else {
+        warn!(
+            "Replay diverged: {} enemies killed, {} when recorded",
+            enemy_counts.killed, playback.replay.killed
+        );
+    }
+}
+
+/// Adds this tick's intents to the recording, starting a new one whenever
+/// a level spawns its players.
+fn record(
+    mut recorder: ResMut<Recorder>,
+    seed: Res<LevelSeed>,
+    mode: Res<GameMode>,
+    progress: Res<CampaignProgress>,
+    added: Query<(), Added<Player>>,
+    query: Query<(&Player, &Intent)>,
+) {
+    if !added.is_empty() {
+        recorder.replay = Some(Replay {
+            seed: seed.0,
+            mode: *mode,
+            level: progress.level,
+            players: query.iter().count(),
+            killed: 0,
+            ticks: Vec::new(),
+        });
+    }
+    let Some(replay) = recorder.replay.as_mut() else {
+        return;
+    };
+    // Players who are down keep their slot with an empty intent.
+    let mut intents = vec![Intent::default(); replay.players];
+    for (player, intent) in query.iter() {
+        if let Some(slot) = intents.get_mut(player.index) {
+            *slot = *intent;
+        }
+    }
+    replay.ticks.push(intents);
+}
+
+fn save_recording(mut recorder: ResMut<Recorder>, enemy_counts: Res<EnemyCounts>) {
+    let Recorder { path, replay } = &mut *recorder;
+    let Some(replay) = replay else {
+        return;
+    };
+    replay.killed = enemy_counts.killed;
+    match replay.save(path) {
+        Ok(()) => info!("Saved {} ticks to {}", replay.ticks.len(), path.display()),
+        Err(e) => warn!("Couldn't save replay {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::bindings::Bindings;
+    use crate::enemy::{AiRng, Behaviour, Dying, Enemy, EnemyKind};
+    use crate::gameplay::headless_app;
+    use crate::input::InputDevices;
+    use crate::level::{build_nav_mesh, wall_pieces, LevelConfig, MazeGrid, MazeLayout, Wall};
+    use crate::{enemy, player};
+    use bevy_xpbd_3d::prelude::*;
+
+    const SEED: u64 = 17;
+    const MAZE_SIZE: usize = 6;
+    const TICKS: usize = 1200;
+
+    /// A small open maze with a player in one corner and enemies spread
+    /// around it.  Walls are bare colliders, as there is nothing to draw.
+    fn build_level(
+        mut commands: Commands,
+        assets: ResMut<AssetServer>,
+        mut enemy_counts: ResMut<EnemyCounts>,
+    ) {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let mut maze = MazeLayout::closed(MAZE_SIZE, MAZE_SIZE);
+        maze.remove_random_walls(40, &mut rng);
+        let config = LevelConfig::default();
+        let grid = MazeGrid::new(MAZE_SIZE, MAZE_SIZE, &config);
+        let walls = wall_pieces(&maze, grid, config.wall_height);
+        let (nav_mesh_settings, nav_mesh) = build_nav_mesh(&grid, &walls);
+        for wall in walls {
+            commands.spawn((
+                TransformBundle::from_transform(wall.transform),
+                RigidBody::Static,
+                wall.collider,
+                Wall,
+            ));
+        }
+        commands.spawn((
+            TransformBundle::default(),
+            RigidBody::Static,
+            Collider::cuboid(500.0, 0.01, 500.0),
+        ));
+
+        let start = grid.cell_center((0, 0), 0.0);
+        player::spawn_player(
+            &mut commands,
+            &assets,
+            start.x,
+            start.z,
+            config.ammo_regen_time,
+            &Bindings::default(),
+            0,
+            1,
+            InputDevices {
+                keyboard: true,
+                gamepad: None,
+            },
+        );
+        let ranged = EnemyKind {
+            behaviour: Behaviour::Ranged {
+                standoff: 8.0,
+                fire_period: 1.0,
+            },
+            ..default()
+        };
+        let spawns = [(5, 5), (5, 0), (0, 5), (3, 3), (2, 4)];
+        for (i, cell) in spawns.into_iter().enumerate() {
+            let kind = if i % 2 == 0 {
+                EnemyKind::default()
+            } else {
+                ranged.clone()
+            };
+            let position = grid.cell_center(cell, 0.0);
+            enemy::spawn_enemy(&mut commands, &assets, 0.0, &kind, position.x, position.z);
+            enemy_counts.count += 1;
+        }
+        commands.insert_resource(nav_mesh_settings);
+        commands.insert_resource(nav_mesh);
+        commands.insert_resource(maze);
+        commands.insert_resource(grid);
+        commands.insert_resource(AiRng(StdRng::seed_from_u64(SEED)));
+    }
+
+    /// Stands in for a player at the keyboard: wanders round the maze for
+    /// `TICKS` ticks, shooting at the closest enemy as it goes.
+    fn play_by_script(
+        mut tick: Local<usize>,
+        mut query: Query<(&mut Intent, &Transform), With<Player>>,
+        enemies: Query<&Transform, (With<Enemy>, Without<Dying>)>,
+    ) {
+        let directions = [Vec3::X, Vec3::Z, Vec3::NEG_X, Vec3::NEG_Z];
+        for (mut intent, xform) in query.iter_mut() {
+            let closest = enemies
+                .iter()
+                .map(|enemy| enemy.translation - xform.translation)
+                .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+            *intent = if *tick < TICKS {
+                Intent {
+                    movement: directions[*tick / 80 % directions.len()] * 0.6,
+                    aim: closest.map(|offset| Vec3::new(offset.x, 0.0, offset.z)),
+                    fire: tick.is_multiple_of(12),
+                }
+            } else {
+                Intent::default()
+            };
+        }
+        *tick += 1;
+    }
+
+    /// The test level at 60fps, so frames don't line up with the 64Hz
+    /// ticks, with replays handled as in the game.
+    fn app() -> App {
+        let mut app = headless_app(Duration::from_secs_f64(1.0 / 60.0));
+        app.add_plugins(ReplayPlugin)
+            .insert_resource(LevelSeed(SEED))
+            .init_resource::<GameMode>()
+            .init_resource::<CampaignProgress>()
+            .add_systems(Startup, build_level);
+        app
+    }
+
+    #[test]
+    fn a_saved_replay_plays_back_to_the_same_kills() {
+        let path =
+            std::env::temp_dir().join(format!("maze_shooter-{}.replay.ron", std::process::id()));
+
+        let mut recording = app();
+        recording
+            .insert_resource(Recorder {
+                path: path.clone(),
+                replay: None,
+            })
+            .add_systems(
+                FixedUpdate,
+                play_by_script
+                    .in_set(ControllerSet)
+                    .in_set(GameplaySet)
+                    .before(record),
+            );
+        while recording.world.resource::<Time<Fixed>>().elapsed_seconds() < TICKS as f32 / 64.0 {
+            recording.update();
+        }
+        // Leaving the level saves it.
+        recording.insert_resource(NextState(Some(GameState::Title)));
+        recording.update();
+
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(replay.ticks.len() >= TICKS);
+        assert!(replay.killed > 0);
+
+        let mut playback = app();
+        playback.insert_resource(Playback {
+            replay: replay.clone(),
+            tick: 0,
+            started: true,
+            finished: false,
+        });
+        for _ in 0..2 * TICKS {
+            if playback.world.resource::<Playback>().finished {
+                break;
+            }
+            playback.update();
+        }
+        assert!(playback.world.resource::<Playback>().finished);
+        assert_eq!(
+            playback.world.resource::<EnemyCounts>().killed,
+            replay.killed
+        );
+    }
+}
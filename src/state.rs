@@ -0,0 +1,43 @@
+use bevy::prelude::*;
+use bevy_xpbd_3d::prelude::*;
+
+#[derive(States, Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum GameState {
+    #[default]
+    Title,
+    Controls,
+    Playing,
+    Paused,
+    Victory,
+    GameOver,
+}
+
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .add_systems(OnEnter(GameState::Playing), resume_gameplay)
+            .add_systems(OnExit(GameState::Playing), pause_gameplay);
+    }
+}
+
+/// Freezes the game clock and the physics clock whenever we leave `Playing`, so
+/// menus and results screens don't let enemies keep moving or ammo regenerate.
+fn pause_gameplay(mut time: ResMut<Time<Virtual>>, mut physics: ResMut<Time<Physics>>) {
+    time.pause();
+    physics.pause();
+}
+
+fn resume_gameplay(mut time: ResMut<Time<Virtual>>, mut physics: ResMut<Time<Physics>>) {
+    time.unpause();
+    physics.unpause();
+}
+
+/// Despawns every entity tagged with `T`.  Used to tear down screens and
+/// levels when leaving a state.
+pub fn despawn_with<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
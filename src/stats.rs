@@ -0,0 +1,19 @@
+use bevy::prelude::*;
+
+/// Numbers collected over a single run, shown on the results screen.
+#[derive(Resource, Default, PartialEq, Debug)]
+pub struct RunStats {
+    pub started: f32,
+    pub shots_fired: u32,
+    pub hits: u32,
+}
+
+impl RunStats {
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            0.0
+        } else {
+            self.hits as f32 / self.shots_fired as f32
+        }
+    }
+}